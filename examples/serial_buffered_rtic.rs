@@ -0,0 +1,82 @@
+//! Echoes received bytes back using the interrupt driven `BufferedSerial`
+#![no_main]
+#![no_std]
+
+extern crate panic_rtt_target;
+
+use rtt_target::{rprint, rprintln};
+use stm32l4xx_hal::{
+    pac::{self, USART2},
+    prelude::*,
+    serial::{BufferedSerial, Config, Serial},
+};
+
+#[rtic::app(device = stm32l4xx_hal::pac)]
+const APP: () = {
+    struct Resources {
+        serial: BufferedSerial<USART2, (gpio_pins::Tx, gpio_pins::Rx)>,
+    }
+
+    #[init]
+    fn init(_: init::Context) -> init::LateResources {
+        static mut RX_BUFFER: [u8; 64] = [0; 64];
+        static mut TX_BUFFER: [u8; 64] = [0; 64];
+
+        rtt_target::rtt_init_print!();
+        rprint!("Initializing... ");
+
+        let p = pac::Peripherals::take().unwrap();
+
+        let mut rcc = p.RCC.constrain();
+        let mut flash = p.FLASH.constrain();
+        let mut pwr = p.PWR.constrain(&mut rcc.apb1r1);
+
+        let clocks = rcc.cfgr.freeze(&mut flash.acr, &mut pwr);
+
+        let mut gpioa = p.GPIOA.split(&mut rcc.ahb2);
+
+        let tx_pin = gpioa.pa2.into_af7(&mut gpioa.moder, &mut gpioa.afrl);
+        let rx_pin = gpioa.pa3.into_af7(&mut gpioa.moder, &mut gpioa.afrl);
+
+        let serial = Serial::usart2(
+            p.USART2,
+            (tx_pin, rx_pin),
+            Config::default().baudrate(115_200.bps()),
+            clocks,
+            &mut rcc.apb1r1,
+        )
+        .into_buffered(RX_BUFFER, TX_BUFFER);
+
+        rprintln!("done.");
+
+        init::LateResources { serial }
+    }
+
+    #[idle(resources = [serial])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let mut buffer = [0; 16];
+
+        loop {
+            cx.resources.serial.lock(|serial| {
+                let len = serial.read(&mut buffer);
+                serial.write(&buffer[..len]);
+
+                if len > 0 {
+                    rprintln!("Echoed {} bytes, stats: {:?}", len, serial.stats());
+                }
+            });
+        }
+    }
+
+    #[task(binds = USART2, resources = [serial])]
+    fn usart2(cx: usart2::Context) {
+        cx.resources.serial.on_interrupt();
+    }
+};
+
+mod gpio_pins {
+    use stm32l4xx_hal::gpio::{Alternate, Floating, Input, AF7, PA2, PA3};
+
+    pub type Tx = PA2<Alternate<AF7, Input<Floating>>>;
+    pub type Rx = PA3<Alternate<AF7, Input<Floating>>>;
+}
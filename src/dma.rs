@@ -63,6 +63,10 @@ where
             matching_character,
         }
     }
+
+    pub(crate) fn payload_mut(&mut self) -> &mut PAYLOAD {
+        &mut self.payload
+    }
 }
 
 impl<BUFFER, PAYLOAD, CHANNEL, const N: usize> FrameReader<BUFFER, RxDma<PAYLOAD, CHANNEL>, N>
@@ -97,6 +101,14 @@ where
             payload,
         }
     }
+
+    /// Creates a frame sender whose DMA transfer of `frame` has already been started
+    pub(crate) fn sending(payload: PAYLOAD, frame: BUFFER) -> FrameSender<BUFFER, PAYLOAD, N> {
+        Self {
+            buffer: Some(frame),
+            payload,
+        }
+    }
 }
 
 /// Data type for holding data frames for the Serial.
@@ -378,6 +390,10 @@ impl<MODE, BUFFER, PAYLOAD> Transfer<MODE, BUFFER, PAYLOAD>
 where
     PAYLOAD: TransferPayload,
 {
+    pub(crate) fn payload_mut(&mut self) -> &mut PAYLOAD {
+        &mut self.payload
+    }
+
     pub(crate) fn extract_inner_without_drop(self) -> (BUFFER, PAYLOAD) {
         // `Transfer` needs to have a `Drop` implementation, because we accept
         // managed buffers that can free their memory on drop. Because of that
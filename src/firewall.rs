@@ -0,0 +1,203 @@
+//! Firewall
+//!
+//! The firewall protects a code segment and a non-volatile data segment in the flash memory, and
+//! a volatile data segment in SRAM1, against the accesses made from outside of the code segment.
+//! Any such access resets the device. The code segment can only be entered through its call gate,
+//! at its start address + 4.
+//!
+//! Once enabled, the firewall can only be disabled by a reset.
+//!
+//! ```
+//! let firewall = dp.FIREWALL.enable(
+//!     firewall::Config::new()
+//!         .code(0x0804_0000, 0x8000)
+//!         .non_volatile_data(0x0804_8000, 0x800)
+//!         .volatile_data(0x2000_0000, 0x400),
+//!     &mut rcc.apb2,
+//! )?;
+//! ```
+
+use crate::rcc::APB2;
+use crate::stm32::{FIREWALL, SYSCFG};
+
+const FLASH_BASE: usize = 0x0800_0000;
+const SRAM1_BASE: usize = 0x2000_0000;
+
+/// Segment of the flash memory or SRAM1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Segment {
+    /// Address of the first byte of the segment
+    pub start: usize,
+    /// Length of the segment in bytes
+    pub length: usize,
+}
+
+/// Firewall configuration error
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The start or the length of a segment is not a multiple of 256 bytes in the flash memory,
+    /// or 64 bytes in SRAM1
+    NotAligned,
+    /// A segment is not in the flash memory or in SRAM1, or is too long
+    OutOfRange,
+}
+
+/// Firewall configuration
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    code: Option<Segment>,
+    non_volatile_data: Option<Segment>,
+    volatile_data: Option<Segment>,
+    volatile_data_shared: bool,
+    volatile_data_executable: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// Configuration with no segment
+    pub fn new() -> Self {
+        Config {
+            code: None,
+            non_volatile_data: None,
+            volatile_data: None,
+            volatile_data_shared: false,
+            volatile_data_executable: false,
+        }
+    }
+
+    /// Code segment in the flash memory, 256 bytes aligned
+    pub fn code(mut self, start: usize, length: usize) -> Self {
+        self.code = Some(Segment { start, length });
+        self
+    }
+
+    /// Non-volatile data segment in the flash memory, 256 bytes aligned
+    pub fn non_volatile_data(mut self, start: usize, length: usize) -> Self {
+        self.non_volatile_data = Some(Segment { start, length });
+        self
+    }
+
+    /// Volatile data segment in SRAM1, 64 bytes aligned
+    pub fn volatile_data(mut self, start: usize, length: usize) -> Self {
+        self.volatile_data = Some(Segment { start, length });
+        self
+    }
+
+    /// The volatile data segment can be read and written from outside of the code segment (VDS)
+    pub fn volatile_data_shared(mut self, shared: bool) -> Self {
+        self.volatile_data_shared = shared;
+        self
+    }
+
+    /// The volatile data segment can be executed from (VDE), when it is not shared
+    pub fn volatile_data_executable(mut self, executable: bool) -> Self {
+        self.volatile_data_executable = executable;
+        self
+    }
+}
+
+/// Extension trait to enable the firewall
+pub trait FirewallExt {
+    /// Configures the segments and enables the firewall, which can only be disabled by a reset
+    fn enable(self, config: Config, apb2: &mut APB2) -> Result<Firewall, Error>;
+}
+
+impl FirewallExt for FIREWALL {
+    fn enable(self, config: Config, apb2: &mut APB2) -> Result<Firewall, Error> {
+        // The start address and length fields, checked before anything is written
+        let code = flash_segment(config.code)?;
+        let non_volatile_data = flash_segment(config.non_volatile_data)?;
+        let volatile_data = match config.volatile_data {
+            Some(segment) => {
+                let start = segment
+                    .start
+                    .checked_sub(SRAM1_BASE)
+                    .ok_or(Error::OutOfRange)?;
+
+                if start % 64 != 0 || segment.length % 64 != 0 {
+                    return Err(Error::NotAligned);
+                }
+                if start + segment.length > 0x1_0000 || segment.length >= 0x1_0000 {
+                    return Err(Error::OutOfRange);
+                }
+
+                ((start >> 6) as u16, (segment.length >> 6) as u16)
+            }
+            None => (0, 0),
+        };
+
+        apb2.enr()
+            .modify(|_, w| w.firewallen().set_bit().syscfgen().set_bit());
+
+        self.cssa.write(|w| unsafe { w.add().bits(code.0) });
+        self.csl.write(|w| unsafe { w.leng().bits(code.1) });
+        self.nvdssa
+            .write(|w| unsafe { w.add().bits(non_volatile_data.0) });
+        self.nvdsl
+            .write(|w| unsafe { w.leng().bits(non_volatile_data.1) });
+        self.vdssa
+            .write(|w| unsafe { w.add().bits(volatile_data.0) });
+        self.vdsl
+            .write(|w| unsafe { w.leng().bits(volatile_data.1) });
+        self.cr.modify(|_, w| {
+            w.vds()
+                .bit(config.volatile_data_shared)
+                .vde()
+                .bit(config.volatile_data_executable)
+        });
+
+        // The segments must be configured before the firewall is enabled
+        // NOTE(unsafe) FWDIS can only be cleared, and is not written by the rest of the HAL
+        unsafe { (*SYSCFG::ptr()).cfgr1.modify(|_, w| w.fwdis().clear_bit()) };
+
+        Ok(Firewall { firewall: self })
+    }
+}
+
+/// Start address and length fields of a flash memory segment
+fn flash_segment(segment: Option<Segment>) -> Result<(u16, u16), Error> {
+    let segment = match segment {
+        Some(segment) => segment,
+        None => return Ok((0, 0)),
+    };
+
+    let start = segment
+        .start
+        .checked_sub(FLASH_BASE)
+        .ok_or(Error::OutOfRange)?;
+
+    if start % 256 != 0 || segment.length % 256 != 0 {
+        return Err(Error::NotAligned);
+    }
+    if start + segment.length > 0x100_0000 || segment.length >= 0x40_0000 {
+        return Err(Error::OutOfRange);
+    }
+
+    Ok(((start >> 8) as u16, (segment.length >> 8) as u16))
+}
+
+/// Enabled firewall
+pub struct Firewall {
+    firewall: FIREWALL,
+}
+
+impl Firewall {
+    /// Sets the pre-arm bit (FPA), the firewall closes when the code segment is left with FPA set
+    /// and the device resets otherwise
+    ///
+    /// FPA can only be written from the code segment while the firewall is open.
+    pub fn set_pre_arm(&mut self, armed: bool) {
+        self.firewall.cr.modify(|_, w| w.fpa().bit(armed));
+    }
+
+    /// Returns `true` if the firewall is enabled
+    pub fn is_enabled(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*SYSCFG::ptr()).cfgr1.read().fwdis().bit_is_clear() }
+    }
+}
@@ -30,12 +30,18 @@
 
 #![deny(missing_docs)]
 
-use crate::stm32::{flash, FLASH};
+use crate::signature::FlashSize;
+use crate::stm32::{flash, DBGMCU, FLASH, SYSCFG};
 use crate::traits::flash as flash_trait;
 use core::convert::TryInto;
-use core::{mem, ops::Drop, ptr};
+use core::{cmp, mem, ops::Drop, ops::Range, ptr};
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
 pub use flash_trait::{Error, FlashPage, Read, WriteErase};
 
+pub mod kv;
+
 /// Extension trait to constrain the FLASH peripheral
 pub trait FlashExt {
     /// Constrains the FLASH peripheral to play nicely with the other abstractions
@@ -56,6 +62,11 @@ impl FlashExt for FLASH {
             pcrop1er: PCROP1ER {},
             wrp1ar: WRP1AR {},
             wrp1br: WRP1BR {},
+            optr: OPTR {},
+            pcrop2sr: PCROP2SR {},
+            pcrop2er: PCROP2ER {},
+            wrp2ar: WRP2AR {},
+            wrp2br: WRP2BR {},
         }
     }
 }
@@ -84,6 +95,16 @@ pub struct Parts {
     pub wrp1ar: WRP1AR,
     /// Opaque WRP1BR register
     pub wrp1br: WRP1BR,
+    /// Opaque OPTR register
+    pub optr: OPTR,
+    /// Opaque PCROP2SR register
+    pub pcrop2sr: PCROP2SR,
+    /// Opaque PCROP2ER register
+    pub pcrop2er: PCROP2ER,
+    /// Opaque WRP2AR register
+    pub wrp2ar: WRP2AR,
+    /// Opaque WRP2BR register
+    pub wrp2br: WRP2BR,
 }
 
 macro_rules! generate_register {
@@ -118,10 +139,233 @@ generate_register!(PCROP1SR, pcrop1sr);
 generate_register!(PCROP1ER, pcrop1er);
 generate_register!(WRP1AR, wrp1ar);
 generate_register!(WRP1BR, wrp1br);
+generate_register!(OPTR, optr);
+generate_register!(PCROP2SR, pcrop2sr);
+generate_register!(PCROP2ER, pcrop2er);
+generate_register!(WRP2AR, wrp2ar);
+generate_register!(WRP2BR, wrp2br);
 
 const FLASH_KEY1: u32 = 0x4567_0123;
 const FLASH_KEY2: u32 = 0xCDEF_89AB;
 
+const OPTION_KEY1: u32 = 0x0819_2A3B;
+const OPTION_KEY2: u32 = 0x4C5D_6E7F;
+
+/// Size of a flash page in bytes
+pub const PAGE_SIZE: usize = 2048;
+
+/// Number of double-words of a row programmed by [`FlashProgramming::fast_program`]
+pub const ROW_DWORDS: usize = 32;
+
+const SYSTEM_MEMORY_ADDRESS: usize = 0x1FFF_0000;
+
+const CR_FSTPG: u32 = 1 << 18;
+const SR_BSY: u32 = 1 << 16;
+
+/// Bank of the flash memory
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bank {
+    /// Bank 1, mapped at 0x0800_0000 unless the banks are swapped
+    Bank1,
+    /// Bank 2
+    Bank2,
+}
+
+/// The device is a STM32L47x/L48x (DEV_ID 0x415) or STM32L49x/L4Ax (DEV_ID 0x461), whose flash
+/// memory controller has the bank 2 option bytes
+fn has_bank2() -> bool {
+    // NOTE(unsafe) read only access to a register that is not written by the HAL
+    let dev_id = unsafe { (*DBGMCU::ptr()).idcode.read().dev_id().bits() };
+    dev_id == 0x415 || dev_id == 0x461
+}
+
+/// Checks that the `len` bytes at `address` are in the flash memory of the device
+fn check_range(address: usize, len: usize) -> flash_trait::Result {
+    let start = FlashPage(0).to_address();
+    let end = start + Geometry::get().size;
+
+    match address.checked_add(len) {
+        Some(last) if address >= start && last <= end => Ok(()),
+        _ => Err(flash_trait::Error::PageOutOfRange),
+    }
+}
+
+/// Organization of the flash memory
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Geometry {
+    /// Size of the flash memory in bytes
+    pub size: usize,
+    /// The flash memory is split in two banks of `size / 2` bytes
+    pub dual_bank: bool,
+}
+
+impl Geometry {
+    /// Reads the organization of the flash memory of the device
+    ///
+    /// The STM32L47x/L48x/L49x/L4Ax devices have two banks, always with 1 MB of flash memory and
+    /// if the DUALBANK option bit is set with less. The other devices have a single bank.
+    pub fn get() -> Self {
+        // NOTE(unsafe) read only access to a register that is not written by the HAL
+        let dualbank = unsafe { (*FLASH::ptr()).optr.read().dualbank().bit_is_set() };
+        let size = FlashSize::get().bytes();
+
+        Geometry {
+            size,
+            dual_bank: has_bank2() && (size >= 1024 * 1024 || dualbank),
+        }
+    }
+
+    /// Size of a bank in bytes
+    pub fn bank_size(&self) -> usize {
+        if self.dual_bank {
+            self.size / 2
+        } else {
+            self.size
+        }
+    }
+
+    /// Number of pages of the flash memory
+    pub fn pages(&self) -> usize {
+        self.size / PAGE_SIZE
+    }
+
+    /// Address of the byte at `offset` in `bank`, `swapped` if bank 2 is mapped at 0x0800_0000
+    pub fn address(&self, bank: Bank, offset: usize, swapped: bool) -> usize {
+        let second = self.dual_bank && ((bank == Bank::Bank2) != swapped);
+
+        FlashPage(0).to_address() + if second { self.bank_size() } else { 0 } + offset
+    }
+
+    /// Bank of `address` and its offset in the bank, `swapped` if bank 2 is mapped at 0x0800_0000
+    pub fn bank_offset(&self, address: usize, swapped: bool) -> Option<(Bank, usize)> {
+        let offset = address.checked_sub(FlashPage(0).to_address())?;
+
+        if offset >= self.size {
+            return None;
+        }
+
+        let second = offset >= self.bank_size();
+        let bank = if self.dual_bank && (second != swapped) {
+            Bank::Bank2
+        } else {
+            Bank::Bank1
+        };
+
+        Some((bank, offset % self.bank_size()))
+    }
+
+    /// Bank and page number in the bank of `page`, `swapped` if bank 2 is mapped at 0x0800_0000
+    pub fn locate(&self, page: FlashPage, swapped: bool) -> Option<(Bank, u8)> {
+        if page.0 >= self.pages() {
+            return None;
+        }
+
+        let pages_per_bank = self.bank_size() / PAGE_SIZE;
+        let second = page.0 >= pages_per_bank;
+        let bank = if self.dual_bank && (second != swapped) {
+            Bank::Bank2
+        } else {
+            Bank::Bank1
+        };
+
+        Some((bank, (page.0 % pages_per_bank) as u8))
+    }
+}
+
+/// Bank mapped at 0x0800_0000, bank 2 after booting from it (BFB2 option bit) or when SYSCFG
+/// swaps the banks
+pub fn mapped_bank() -> Bank {
+    // NOTE(unsafe) atomic read with no side effects
+    if unsafe { (*SYSCFG::ptr()).memrmp.read().fb_mode().bit_is_set() } {
+        Bank::Bank2
+    } else {
+        Bank::Bank1
+    }
+}
+
+/// Flash events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A program or erase operation completed (EOP)
+    EndOfOperation,
+    /// A program or erase operation failed (OPERR)
+    OperationError,
+}
+
+/// Kind of ECC error
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EccErrorKind {
+    /// Single bit error, corrected when it was read (ECCC)
+    Corrected,
+    /// Double bit error, which cannot be corrected and raises an NMI (ECCD)
+    Uncorrectable,
+}
+
+/// ECC error detected when reading the flash memory
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EccError {
+    /// Single or double error
+    pub kind: EccErrorKind,
+    /// Address of the double-word that failed, in the current memory map
+    pub address: usize,
+    /// Bank of the double-word that failed
+    pub bank: Bank,
+    /// The error is in the system flash memory instead of the main flash memory
+    pub system_flash: bool,
+}
+
+impl ECCR {
+    /// Returns and clears the ECC error, an uncorrectable error is returned first when both
+    /// kinds are pending
+    pub fn ecc_error(&mut self) -> Option<EccError> {
+        let eccr = self.eccr();
+        let r = eccr.read();
+
+        let kind = if r.eccd().bit_is_set() {
+            eccr.modify(|_, w| w.eccc().clear_bit().eccd().set_bit());
+            EccErrorKind::Uncorrectable
+        } else if r.eccc().bit_is_set() {
+            eccr.modify(|_, w| w.eccc().set_bit().eccd().clear_bit());
+            EccErrorKind::Corrected
+        } else {
+            return None;
+        };
+
+        let bank = if r.bk_ecc().bit_is_set() {
+            Bank::Bank2
+        } else {
+            Bank::Bank1
+        };
+        let offset = r.addr_ecc().bits() as usize & !(mem::size_of::<u64>() - 1);
+        let system_flash = r.sysf_ecc().bit_is_set();
+
+        Some(EccError {
+            kind,
+            address: if system_flash {
+                SYSTEM_MEMORY_ADDRESS + offset
+            } else {
+                Geometry::get().address(bank, offset, mapped_bank() == Bank::Bank2)
+            },
+            bank,
+            system_flash,
+        })
+    }
+
+    /// Enables the FLASH interrupt on corrected ECC errors (ECCIE)
+    ///
+    /// Uncorrectable errors always raise an NMI.
+    pub fn listen_corrected(&mut self) {
+        self.eccr()
+            .modify(|_, w| w.eccie().set_bit().eccc().clear_bit().eccd().clear_bit());
+    }
+
+    /// Disables the FLASH interrupt on corrected ECC errors
+    pub fn unlisten_corrected(&mut self) {
+        self.eccr()
+            .modify(|_, w| w.eccie().clear_bit().eccc().clear_bit().eccd().clear_bit());
+    }
+}
+
 impl KEYR {
     /// Unlock the flash registers via KEYR to access the flash programming
     pub fn unlock_flash<'a>(
@@ -136,13 +380,445 @@ impl KEYR {
         }
 
         if cr.cr().read().lock().bit_is_clear() {
-            Ok(FlashProgramming { sr, cr })
+            Ok(FlashProgramming {
+                sr,
+                cr,
+                pending: None,
+            })
+        } else {
+            Err(Error::Failure)
+        }
+    }
+}
+
+impl OPTKEYR {
+    /// Unlock the option bytes via OPTKEYR, the flash must be unlocked with
+    /// [`KEYR::unlock_flash`]
+    pub fn unlock_options<'a, 'b>(
+        &'a mut self,
+        flash: &'a mut FlashProgramming<'b>,
+        optr: &'a mut OPTR,
+    ) -> Result<OptionProgramming<'a, 'b>, Error> {
+        let optkeyr = self.optkeyr();
+        unsafe {
+            optkeyr.write(|w| w.bits(OPTION_KEY1));
+            optkeyr.write(|w| w.bits(OPTION_KEY2));
+        }
+
+        if flash.cr.cr().read().optlock().bit_is_clear() {
+            Ok(OptionProgramming { flash, optr })
         } else {
             Err(Error::Failure)
         }
     }
 }
 
+/// Read protection level (RDP)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadProtection {
+    /// No protection
+    Level0,
+    /// The flash memory cannot be read by the debugger or when booting from RAM or the system
+    /// memory, going back to level 0 mass erases the flash memory
+    Level1,
+    /// Level 1 with the debug disabled, this level is permanent
+    Level2,
+}
+
+/// Brown out reset threshold
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum BorLevel {
+    /// Around 1.7 V
+    Level0 = 0b000,
+    /// Around 2.0 V
+    Level1 = 0b001,
+    /// Around 2.2 V
+    Level2 = 0b010,
+    /// Around 2.5 V
+    Level3 = 0b011,
+    /// Around 2.8 V
+    Level4 = 0b100,
+}
+
+/// Write protected area, pages `start..=end` of a bank
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WrpArea {
+    /// First page, relative to the start of the bank
+    pub start: u8,
+    /// Last page, relative to the start of the bank
+    pub end: u8,
+}
+
+impl WrpArea {
+    /// No page protected
+    pub const DISABLED: WrpArea = WrpArea {
+        start: 0xFF,
+        end: 0x00,
+    };
+
+    /// Returns `true` if some pages are protected
+    pub fn is_enabled(&self) -> bool {
+        self.start <= self.end
+    }
+
+    /// Area of the pages holding `addresses`, which must be in a single bank
+    pub fn covering(
+        geometry: &Geometry,
+        addresses: Range<usize>,
+        swapped: bool,
+    ) -> Option<(Bank, WrpArea)> {
+        let (bank, first, last) = bank_range(geometry, addresses, swapped)?;
+
+        Some((
+            bank,
+            WrpArea {
+                start: (first / PAGE_SIZE) as u8,
+                end: (last / PAGE_SIZE) as u8,
+            },
+        ))
+    }
+}
+
+/// Bank of `addresses` and the offsets of their first and last bytes in the bank
+fn bank_range(
+    geometry: &Geometry,
+    addresses: Range<usize>,
+    swapped: bool,
+) -> Option<(Bank, usize, usize)> {
+    if addresses.start >= addresses.end {
+        return None;
+    }
+
+    let (bank, first) = geometry.bank_offset(addresses.start, swapped)?;
+    let (last_bank, last) = geometry.bank_offset(addresses.end - 1, swapped)?;
+
+    if bank == last_bank {
+        Some((bank, first, last))
+    } else {
+        None
+    }
+}
+
+/// Proprietary code readout protected area (PCROP), double-words `start..=end` of a bank
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PcropArea {
+    /// First double-word, relative to the start of the bank
+    pub start: u16,
+    /// Last double-word, relative to the start of the bank
+    pub end: u16,
+}
+
+impl PcropArea {
+    /// No double-word protected
+    pub const DISABLED: PcropArea = PcropArea {
+        start: 0xFFFF,
+        end: 0x0000,
+    };
+
+    /// Returns `true` if some double-words are protected
+    pub fn is_enabled(&self) -> bool {
+        self.start <= self.end
+    }
+
+    /// Area of the double-words holding `addresses`, which must be in a single bank
+    pub fn covering(
+        geometry: &Geometry,
+        addresses: Range<usize>,
+        swapped: bool,
+    ) -> Option<(Bank, PcropArea)> {
+        let (bank, first, last) = bank_range(geometry, addresses, swapped)?;
+
+        Some((
+            bank,
+            PcropArea {
+                start: (first / mem::size_of::<u64>()) as u16,
+                end: (last / mem::size_of::<u64>()) as u16,
+            },
+        ))
+    }
+}
+
+/// User option bytes, the other option bits are left unchanged
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OptionBytes {
+    /// Read protection level
+    pub read_protection: ReadProtection,
+    /// Brown out reset threshold
+    pub bor_level: BorLevel,
+    /// A reset is generated when entering Stop mode (nRST_STOP cleared)
+    pub reset_on_stop: bool,
+    /// A reset is generated when entering Standby mode (nRST_STDBY cleared)
+    pub reset_on_standby: bool,
+    /// Dual bank mode of the 256 kB and 512 kB devices with two banks (DUALBANK)
+    pub dual_bank: bool,
+    /// Boot from bank 2 if it holds a valid stack pointer (BFB2)
+    pub boot_from_bank2: bool,
+    /// First write protected area of bank 1
+    pub wrp1a: WrpArea,
+    /// Second write protected area of bank 1
+    pub wrp1b: WrpArea,
+    /// First write protected area of bank 2
+    pub wrp2a: WrpArea,
+    /// Second write protected area of bank 2
+    pub wrp2b: WrpArea,
+    /// Proprietary code readout protected area of bank 1
+    pub pcrop1: PcropArea,
+    /// Proprietary code readout protected area of bank 2
+    pub pcrop2: PcropArea,
+    /// The PCROP areas are erased when the read protection goes back from level 1 to level 0
+    pub pcrop_erase_on_rdp_regression: bool,
+}
+
+impl OPTR {
+    /// Reads the option bytes loaded at the last reset or option bytes launch
+    pub fn read_options(&mut self) -> OptionBytes {
+        // NOTE(unsafe) read only access to the option registers, which are only written with
+        // the OPTR proxy
+        let flash = unsafe { &*FLASH::ptr() };
+        let optr = flash.optr.read();
+        let wrp = |strt: u8, end: u8| WrpArea { start: strt, end };
+        let wrp1ar = flash.wrp1ar.read();
+        let wrp1br = flash.wrp1br.read();
+        let wrp2ar = flash.wrp2ar.read();
+        let wrp2br = flash.wrp2br.read();
+        let pcrop1er = flash.pcrop1er.read();
+
+        OptionBytes {
+            read_protection: match optr.rdp().bits() {
+                0xAA => ReadProtection::Level0,
+                0xCC => ReadProtection::Level2,
+                _ => ReadProtection::Level1,
+            },
+            bor_level: match optr.bor_lev().bits() {
+                0b000 => BorLevel::Level0,
+                0b001 => BorLevel::Level1,
+                0b010 => BorLevel::Level2,
+                0b011 => BorLevel::Level3,
+                _ => BorLevel::Level4,
+            },
+            reset_on_stop: optr.n_rst_stop().bit_is_clear(),
+            reset_on_standby: optr.n_rst_stdby().bit_is_clear(),
+            dual_bank: optr.dualbank().bit_is_set(),
+            boot_from_bank2: optr.bfb2().bit_is_set(),
+            wrp1a: wrp(wrp1ar.wrp1a_strt().bits(), wrp1ar.wrp1a_end().bits()),
+            wrp1b: wrp(wrp1br.wrp1b_strt().bits(), wrp1br.wrp1b_end().bits()),
+            wrp2a: wrp(wrp2ar.wrp2a_strt().bits(), wrp2ar.wrp2a_end().bits()),
+            wrp2b: wrp(wrp2br.wrp2b_strt().bits(), wrp2br.wrp2b_end().bits()),
+            pcrop1: PcropArea {
+                start: flash.pcrop1sr.read().pcrop1_strt().bits(),
+                end: pcrop1er.pcrop1_end().bits(),
+            },
+            pcrop2: PcropArea {
+                start: flash.pcrop2sr.read().pcrop2_strt().bits(),
+                end: flash.pcrop2er.read().pcrop2_end().bits(),
+            },
+            pcrop_erase_on_rdp_regression: pcrop1er.pcrop_rdp().bit_is_set(),
+        }
+    }
+}
+
+/// Option bytes programming interface
+pub struct OptionProgramming<'a, 'b> {
+    flash: &'a mut FlashProgramming<'b>,
+    optr: &'a mut OPTR,
+}
+
+impl<'a, 'b> Drop for OptionProgramming<'a, 'b> {
+    fn drop(&mut self) {
+        // Lock on drop
+        self.flash.cr.cr().modify(|_, w| w.optlock().set_bit());
+    }
+}
+
+impl<'a, 'b> OptionProgramming<'a, 'b> {
+    /// Reads the option bytes loaded at the last reset or option bytes launch
+    pub fn read(&mut self) -> OptionBytes {
+        self.optr.read_options()
+    }
+
+    /// Programs `options`, they are loaded at the next reset or with
+    /// [`OptionProgramming::launch`]
+    ///
+    /// Going from read protection level 1 to level 0 mass erases the flash memory, and level 2
+    /// cannot be left. The bank 2 options are ignored on the devices with a single bank.
+    pub fn write(&mut self, options: &OptionBytes) -> flash_trait::Result {
+        self.flash.wait()?;
+
+        // NOTE(unsafe) the option registers are only written with the OPTR proxy, which is
+        // borrowed
+        let flash = unsafe { &*FLASH::ptr() };
+
+        let rdp = match options.read_protection {
+            ReadProtection::Level0 => 0xAA,
+            ReadProtection::Level1 => 0xBB,
+            ReadProtection::Level2 => 0xCC,
+        };
+        self.optr.optr().modify(|_, w| unsafe {
+            w.rdp()
+                .bits(rdp)
+                .bor_lev()
+                .bits(options.bor_level as u8)
+                .n_rst_stop()
+                .bit(!options.reset_on_stop)
+                .n_rst_stdby()
+                .bit(!options.reset_on_standby)
+        });
+        // The bank 2 option bits and registers are reserved on the single bank devices
+        let bank2 = has_bank2();
+        if bank2 {
+            self.optr.optr().modify(|_, w| {
+                w.dualbank()
+                    .bit(options.dual_bank)
+                    .bfb2()
+                    .bit(options.boot_from_bank2)
+            });
+        }
+
+        flash.wrp1ar.write(|w| unsafe {
+            w.wrp1a_strt()
+                .bits(options.wrp1a.start)
+                .wrp1a_end()
+                .bits(options.wrp1a.end)
+        });
+        flash.wrp1br.write(|w| unsafe {
+            w.wrp1b_strt()
+                .bits(options.wrp1b.start)
+                .wrp1b_end()
+                .bits(options.wrp1b.end)
+        });
+        flash
+            .pcrop1sr
+            .write(|w| unsafe { w.pcrop1_strt().bits(options.pcrop1.start) });
+        flash.pcrop1er.write(|w| unsafe {
+            w.pcrop1_end()
+                .bits(options.pcrop1.end)
+                .pcrop_rdp()
+                .bit(options.pcrop_erase_on_rdp_regression)
+        });
+        if bank2 {
+            flash.wrp2ar.write(|w| unsafe {
+                w.wrp2a_strt()
+                    .bits(options.wrp2a.start)
+                    .wrp2a_end()
+                    .bits(options.wrp2a.end)
+            });
+            flash.wrp2br.write(|w| unsafe {
+                w.wrp2b_strt()
+                    .bits(options.wrp2b.start)
+                    .wrp2b_end()
+                    .bits(options.wrp2b.end)
+            });
+            flash
+                .pcrop2sr
+                .write(|w| unsafe { w.pcrop2_strt().bits(options.pcrop2.start) });
+            flash
+                .pcrop2er
+                .write(|w| unsafe { w.pcrop2_end().bits(options.pcrop2.end) });
+        }
+
+        // OPTVERR is also set at reset when the option bytes failed to load, it is cleared so that
+        // only an error of this programming is reported
+        self.flash.sr.sr().write(|w| w.optverr().set_bit());
+        self.flash.cr.cr().modify(|_, w| w.optstrt().set_bit());
+
+        let res = self.flash.wait();
+
+        if self.flash.sr.sr().read().optverr().bit_is_set() {
+            self.flash.sr.sr().write(|w| w.optverr().set_bit());
+            return Err(flash_trait::Error::Illegal);
+        }
+
+        res
+    }
+
+    /// Write protects the pages holding `addresses` with the first unused area of their bank
+    pub fn write_protect(&mut self, addresses: Range<usize>) -> flash_trait::Result {
+        let (bank, area) =
+            WrpArea::covering(&Geometry::get(), addresses, mapped_bank() == Bank::Bank2)
+                .ok_or(flash_trait::Error::PageOutOfRange)?;
+
+        let mut options = self.read();
+        let (a, b) = match bank {
+            Bank::Bank1 => (&mut options.wrp1a, &mut options.wrp1b),
+            Bank::Bank2 => (&mut options.wrp2a, &mut options.wrp2b),
+        };
+
+        if !a.is_enabled() {
+            *a = area;
+        } else if !b.is_enabled() {
+            *b = area;
+        } else {
+            return Err(flash_trait::Error::Failure);
+        }
+
+        self.write(&options)
+    }
+
+    /// Removes the write protection of the pages of `bank`
+    pub fn clear_write_protection(&mut self, bank: Bank) -> flash_trait::Result {
+        let mut options = self.read();
+
+        match bank {
+            Bank::Bank1 => {
+                options.wrp1a = WrpArea::DISABLED;
+                options.wrp1b = WrpArea::DISABLED;
+            }
+            Bank::Bank2 => {
+                options.wrp2a = WrpArea::DISABLED;
+                options.wrp2b = WrpArea::DISABLED;
+            }
+        }
+
+        self.write(&options)
+    }
+
+    /// Protects the code at `addresses` against reads and writes (PCROP), it can only be executed
+    ///
+    /// The area can then only be removed or reduced by a read protection regression from level 1
+    /// to level 0, which also erases it if `erase_on_rdp_regression` is set.
+    pub fn readout_protect(
+        &mut self,
+        addresses: Range<usize>,
+        erase_on_rdp_regression: bool,
+    ) -> flash_trait::Result {
+        let (bank, area) =
+            PcropArea::covering(&Geometry::get(), addresses, mapped_bank() == Bank::Bank2)
+                .ok_or(flash_trait::Error::PageOutOfRange)?;
+
+        let mut options = self.read();
+        match bank {
+            Bank::Bank1 => options.pcrop1 = area,
+            Bank::Bank2 => options.pcrop2 = area,
+        }
+        options.pcrop_erase_on_rdp_regression = erase_on_rdp_regression;
+
+        self.write(&options)
+    }
+
+    /// Selects the bank the device boots from at the next option bytes load, for A/B firmware
+    /// updates
+    ///
+    /// The bank currently mapped at 0x0800_0000 is returned by [`mapped_bank`], so booting from
+    /// the other bank (once it holds a new firmware) is
+    /// `set_boot_bank(other)` followed by [`OptionProgramming::launch`].
+    pub fn set_boot_bank(&mut self, bank: Bank) -> flash_trait::Result {
+        let mut options = self.read();
+        options.boot_from_bank2 = bank == Bank::Bank2;
+        self.write(&options)
+    }
+
+    /// Loads the programmed option bytes, this resets the device
+    pub fn launch(self) -> ! {
+        self.flash.cr.cr().modify(|_, w| w.obl_launch().set_bit());
+
+        loop {
+            // The device resets
+            cortex_m::asm::nop();
+        }
+    }
+}
+
 impl FlashPage {
     /// This gives the starting address of a flash page in physical address
     pub const fn to_address(&self) -> usize {
@@ -154,6 +830,17 @@ impl FlashPage {
 pub struct FlashProgramming<'a> {
     sr: &'a mut SR,
     cr: &'a mut CR,
+    /// Non-blocking erase or program that has been started and not polled to completion
+    pending: Option<Pending>,
+}
+
+/// Non-blocking operation of [`FlashProgramming`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pending {
+    /// Erase of the page
+    Erase(usize),
+    /// Program of the double-word at the address
+    Program(usize, u64),
 }
 
 impl<'a> Drop for FlashProgramming<'a> {
@@ -192,7 +879,13 @@ impl<'a> WriteErase for FlashProgramming<'a> {
 
         if sr.bsy().bit_is_set() {
             Err(flash_trait::Error::Busy)
-        } else if sr.pgaerr().bit_is_set() || sr.progerr().bit_is_set() || sr.wrperr().bit_is_set()
+        } else if sr.pgaerr().bit_is_set()
+            || sr.progerr().bit_is_set()
+            || sr.wrperr().bit_is_set()
+            || sr.sizerr().bit_is_set()
+            || sr.pgserr().bit_is_set()
+            || sr.miserr().bit_is_set()
+            || sr.fasterr().bit_is_set()
         {
             Err(flash_trait::Error::Illegal)
         } else {
@@ -201,31 +894,19 @@ impl<'a> WriteErase for FlashProgramming<'a> {
     }
 
     fn erase_page(&mut self, page: flash_trait::FlashPage) -> flash_trait::Result {
-        match page.0 {
-            0..=255 => {
-                self.cr.cr().modify(|_, w| unsafe {
-                    w.bker()
-                        .clear_bit()
-                        .pnb()
-                        .bits(page.0 as u8)
-                        .per()
-                        .set_bit()
-                });
-            }
-            256..=511 => {
-                self.cr.cr().modify(|_, w| unsafe {
-                    w.bker()
-                        .set_bit()
-                        .pnb()
-                        .bits((page.0 - 256) as u8)
-                        .per()
-                        .set_bit()
-                });
-            }
-            _ => {
-                return Err(flash_trait::Error::PageOutOfRange);
-            }
-        }
+        // The bank mapped at the address of the page depends on the bank swapping
+        let (bank, pnb) = Geometry::get()
+            .locate(page, mapped_bank() == Bank::Bank2)
+            .ok_or(flash_trait::Error::PageOutOfRange)?;
+
+        self.cr.cr().modify(|_, w| unsafe {
+            w.bker()
+                .bit(bank == Bank::Bank2)
+                .pnb()
+                .bits(pnb)
+                .per()
+                .set_bit()
+        });
 
         self.cr.cr().modify(|_, w| w.start().set_bit());
 
@@ -243,6 +924,7 @@ impl<'a> WriteErase for FlashProgramming<'a> {
 
         self.cr.cr().modify(|_, w| w.pg().set_bit());
 
+        let mut res = Ok(());
         for dword in array {
             unsafe {
                 ptr::write_volatile(address, *dword as u32);
@@ -251,16 +933,20 @@ impl<'a> WriteErase for FlashProgramming<'a> {
                 address = address.add(2);
             }
 
-            self.wait()?;
+            res = self.wait();
+            if res.is_err() {
+                break;
+            }
 
             if self.sr.sr().read().eop().bit_is_set() {
                 self.sr.sr().modify(|_, w| w.eop().clear_bit());
             }
         }
 
+        // PG is cleared on errors as well, a later operation would otherwise see it set
         self.cr.cr().modify(|_, w| w.pg().clear_bit());
 
-        Ok(())
+        res
     }
 
     fn write(&mut self, address: usize, data: &[u8]) -> flash_trait::Result {
@@ -318,6 +1004,125 @@ impl<'a> WriteErase for FlashProgramming<'a> {
 }
 
 impl<'a> FlashProgramming<'a> {
+    /// Starts listening for an `event`
+    pub fn listen(&mut self, event: Event) {
+        self.cr.cr().modify(|_, w| match event {
+            Event::EndOfOperation => w.eopie().set_bit(),
+            Event::OperationError => w.errie().set_bit(),
+        });
+    }
+
+    /// Stops listening for an `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.cr.cr().modify(|_, w| match event {
+            Event::EndOfOperation => w.eopie().clear_bit(),
+            Event::OperationError => w.errie().clear_bit(),
+        });
+    }
+
+    /// Non-blocking erase of `page`
+    ///
+    /// The first call starts the erase, the next calls with the same page return `WouldBlock`
+    /// until it completes, which can be signalled with [`Event::EndOfOperation`]. `Busy` is
+    /// returned while another non-blocking operation has not been polled to completion.
+    pub fn erase_page_nb(&mut self, page: FlashPage) -> nb::Result<(), flash_trait::Error> {
+        let operation = Pending::Erase(page.0);
+        if let Some(res) = self.resume(operation) {
+            return res;
+        }
+
+        let (bank, pnb) = Geometry::get()
+            .locate(page, mapped_bank() == Bank::Bank2)
+            .ok_or(flash_trait::Error::PageOutOfRange)?;
+
+        self.cr.cr().modify(|_, w| unsafe {
+            w.bker()
+                .bit(bank == Bank::Bank2)
+                .pnb()
+                .bits(pnb)
+                .per()
+                .set_bit()
+        });
+        self.cr.cr().modify(|_, w| w.start().set_bit());
+        self.pending = Some(operation);
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Non-blocking program of the double-word `dword` at `address`
+    ///
+    /// The first call starts the programming, the next calls with the same arguments return
+    /// `WouldBlock` until it completes, which can be signalled with [`Event::EndOfOperation`].
+    /// `Busy` is returned while another non-blocking operation has not been polled to completion.
+    pub fn write_native_nb(
+        &mut self,
+        address: usize,
+        dword: u64,
+    ) -> nb::Result<(), flash_trait::Error> {
+        let operation = Pending::Program(address, dword);
+        if let Some(res) = self.resume(operation) {
+            return res;
+        }
+
+        let address = address as *mut u32;
+
+        self.cr.cr().modify(|_, w| w.pg().set_bit());
+
+        unsafe {
+            ptr::write_volatile(address, dword as u32);
+            ptr::write_volatile(address.add(1), (dword >> 32) as u32);
+        }
+        self.pending = Some(operation);
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Polls `operation` if it is the pending non-blocking operation, returns `None` if no
+    /// operation is pending
+    fn resume(&mut self, operation: Pending) -> Option<nb::Result<(), flash_trait::Error>> {
+        match self.pending {
+            Some(pending) if pending == operation => Some(self.poll()),
+            Some(_) => Some(Err(nb::Error::Other(flash_trait::Error::Busy))),
+            None => None,
+        }
+    }
+
+    /// Completes a non-blocking erase or program, clearing the EOP and error flags
+    fn poll(&mut self) -> nb::Result<(), flash_trait::Error> {
+        if self.sr.sr().read().bsy().bit_is_set() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let res = self.status();
+
+        self.pending = None;
+        self.cr
+            .cr()
+            .modify(|_, w| w.per().clear_bit().pg().clear_bit());
+        self.sr.sr().write(|w| {
+            w.eop()
+                .set_bit()
+                .operr()
+                .set_bit()
+                .progerr()
+                .set_bit()
+                .wrperr()
+                .set_bit()
+                .pgaerr()
+                .set_bit()
+                .sizerr()
+                .set_bit()
+                .pgserr()
+                .set_bit()
+                .miserr()
+                .set_bit()
+                .fasterr()
+                .set_bit()
+        });
+
+        res.map_err(nb::Error::Other)
+    }
+
     /// Lock the flash memory controller
     fn lock(&mut self) {
         self.cr.cr().modify(|_, w| w.lock().set_bit());
@@ -342,4 +1147,432 @@ impl<'a> FlashProgramming<'a> {
 
         res
     }
+
+    /// Erase all the pages of `bank`, this is the physical bank regardless of the bank swapping
+    ///
+    /// This erases the current running program if it runs from `bank`.
+    pub fn erase_bank(&mut self, bank: Bank) -> flash_trait::Result {
+        self.cr.cr().modify(|_, w| match bank {
+            Bank::Bank1 => w.mer1().set_bit(),
+            Bank::Bank2 => w.mer2().set_bit(),
+        });
+        self.cr.cr().modify(|_, w| w.start().set_bit());
+
+        let res = self.wait();
+
+        self.cr
+            .cr()
+            .modify(|_, w| w.mer1().clear_bit().mer2().clear_bit());
+
+        res
+    }
+
+    /// Erase the pages `pages`
+    pub fn erase_pages(&mut self, pages: Range<usize>) -> flash_trait::Result {
+        for page in pages {
+            self.erase_page(FlashPage(page))?;
+        }
+
+        Ok(())
+    }
+
+    /// Program rows of 32 double-words with fast programming, `address` must be aligned on a row
+    ///
+    /// Fast programming needs the bank holding the rows to be mass erased first (see
+    /// [`FlashProgramming::erase_bank`]) and reports `Illegal` otherwise, so it is meant to
+    /// program a full image in the other bank. Interrupts are disabled while each row is
+    /// programmed, as the flash memory cannot be read meanwhile, and the programming itself runs
+    /// from RAM. `PageOutOfRange` is returned if the rows do not fit in the flash memory.
+    ///
+    /// The flash clock (HCLK) must be at least 8 MHz, otherwise the programming of a row does not
+    /// keep up with the fast programming timing and fails.
+    pub fn fast_program(
+        &mut self,
+        address: usize,
+        rows: &[[u64; ROW_DWORDS]],
+    ) -> flash_trait::Result {
+        if !address.is_multiple_of(ROW_DWORDS * mem::size_of::<u64>()) {
+            return Err(flash_trait::Error::Illegal);
+        }
+        check_range(address, mem::size_of_val(rows))?;
+
+        self.wait()?;
+
+        let (cr, sr) = {
+            // NOTE(unsafe) only the addresses of the registers, which are borrowed
+            let flash = unsafe { &*FLASH::ptr() };
+            (
+                &flash.cr as *const _ as *mut u32,
+                &flash.sr as *const _ as *const u32,
+            )
+        };
+        let mut address = address as *mut u32;
+
+        for row in rows {
+            // The row is copied to RAM as the flash memory cannot be read while it is programmed
+            let row = *row;
+
+            cortex_m::interrupt::free(|_| unsafe { fast_program_row(cr, sr, address, &row) });
+
+            self.status()?;
+
+            if self.sr.sr().read().eop().bit_is_set() {
+                self.sr.sr().modify(|_, w| w.eop().clear_bit());
+            }
+
+            address = unsafe { address.add(ROW_DWORDS * 2) };
+        }
+
+        Ok(())
+    }
+
+    /// Write `data` at `address` with read-modify-write of the pages it overlaps, the rest of the
+    /// pages is preserved
+    ///
+    /// `buffer` holds a copy of one page at a time. Pages whose content does not change are not
+    /// erased. `PageOutOfRange` is returned if `data` does not fit in the flash memory.
+    pub fn rewrite(
+        &mut self,
+        address: usize,
+        data: &[u8],
+        buffer: &mut [u8; PAGE_SIZE],
+    ) -> flash_trait::Result {
+        // The pages are read back, and reading past the flash memory is a bus fault
+        check_range(address, data.len())?;
+
+        let mut address = address;
+        let mut data = data;
+
+        while !data.is_empty() {
+            let page = FlashPage((address - FlashPage(0).to_address()) / PAGE_SIZE);
+            let page_address = page.to_address();
+            let offset = address - page_address;
+            let len = cmp::min(PAGE_SIZE - offset, data.len());
+
+            self.read(page_address, buffer);
+
+            if buffer[offset..offset + len] != data[..len] {
+                buffer[offset..offset + len].copy_from_slice(&data[..len]);
+
+                self.erase_page(page)?;
+
+                // Erased double-words are left as is
+                for (i, dword) in buffer.chunks_exact(mem::size_of::<u64>()).enumerate() {
+                    let dword = u64::from_ne_bytes(dword.try_into().unwrap());
+
+                    if dword != u64::MAX {
+                        self.write_native(page_address + i * mem::size_of::<u64>(), &[dword])?;
+                    }
+                }
+            }
+
+            address += len;
+            data = &data[len..];
+        }
+
+        Ok(())
+    }
+}
+
+/// Error of a [`FlashRegion`] operation
+#[derive(Copy, Clone, Debug)]
+pub enum RegionError {
+    /// The offset or length is not aligned on the read, write or erase size
+    NotAligned,
+    /// The operation is not within the region
+    OutOfBounds,
+    /// The flash memory controller reported an error
+    Flash(Error),
+}
+
+impl From<Error> for RegionError {
+    fn from(error: Error) -> Self {
+        RegionError::Flash(error)
+    }
+}
+
+impl NorFlashError for RegionError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            RegionError::NotAligned => NorFlashErrorKind::NotAligned,
+            RegionError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            RegionError::Flash(_) => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Pages of the flash memory used with the `embedded-storage` NOR flash traits, offsets are
+/// relative to the first page
+pub struct FlashRegion<'a, 'b> {
+    flash: &'a mut FlashProgramming<'b>,
+    first_page: usize,
+    pages: usize,
+}
+
+impl<'a, 'b> FlashRegion<'a, 'b> {
+    /// Creates a region made of the pages `pages`
+    pub fn new(flash: &'a mut FlashProgramming<'b>, pages: Range<usize>) -> Result<Self, Error> {
+        if pages.start > pages.end || pages.end > Geometry::get().pages() {
+            return Err(Error::PageOutOfRange);
+        }
+
+        Ok(FlashRegion {
+            flash,
+            first_page: pages.start,
+            pages: pages.end - pages.start,
+        })
+    }
+
+    /// Address of the first byte of the region
+    pub fn address(&self) -> usize {
+        FlashPage(self.first_page).to_address()
+    }
+
+    /// Releases the flash programming interface
+    pub fn free(self) -> &'a mut FlashProgramming<'b> {
+        self.flash
+    }
+}
+
+impl<'a, 'b> ErrorType for FlashRegion<'a, 'b> {
+    type Error = RegionError;
+}
+
+impl<'a, 'b> ReadNorFlash for FlashRegion<'a, 'b> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_slice(offset, bytes.len(), Self::READ_SIZE, self.capacity())?;
+
+        Read::read(self.flash, self.address() + offset as usize, bytes);
+
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+}
+
+impl<'a, 'b> NorFlash for FlashRegion<'a, 'b> {
+    const WRITE_SIZE: usize = mem::size_of::<u64>();
+    const ERASE_SIZE: usize = PAGE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(from, to, Self::ERASE_SIZE, self.capacity())?;
+
+        let first = self.first_page + from as usize / PAGE_SIZE;
+        let last = self.first_page + to as usize / PAGE_SIZE;
+        self.flash.erase_pages(first..last)?;
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_slice(offset, bytes.len(), Self::WRITE_SIZE, self.capacity())?;
+
+        WriteErase::write(self.flash, self.address() + offset as usize, bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Checks that `length` bytes at `offset` are in a region of `capacity` bytes and aligned on
+/// `align`
+fn check_slice(
+    offset: u32,
+    length: usize,
+    align: usize,
+    capacity: usize,
+) -> Result<(), RegionError> {
+    let offset = offset as usize;
+
+    if length > capacity || offset > capacity - length {
+        Err(RegionError::OutOfBounds)
+    } else if !offset.is_multiple_of(align) || !length.is_multiple_of(align) {
+        Err(RegionError::NotAligned)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that the bytes `from..to` are in a region of `capacity` bytes and aligned on `align`
+fn check_erase(from: u32, to: u32, align: usize, capacity: usize) -> Result<(), RegionError> {
+    let (from, to) = (from as usize, to as usize);
+
+    if from > to || to > capacity {
+        Err(RegionError::OutOfBounds)
+    } else if from % align != 0 || to % align != 0 {
+        Err(RegionError::NotAligned)
+    } else {
+        Ok(())
+    }
+}
+
+/// Program a row with FSTPG set
+///
+/// This runs from RAM and does not call any function placed in the flash memory, which cannot be
+/// read until the row is programmed. The stores and the BSY poll are written in assembly, as the
+/// `ptr` functions are not inlined without optimizations and live in the flash memory.
+#[inline(never)]
+#[link_section = ".data.flash_fast_program_row"]
+unsafe fn fast_program_row(
+    cr: *mut u32,
+    sr: *const u32,
+    address: *mut u32,
+    row: &[u64; ROW_DWORDS],
+) {
+    #[cfg(target_arch = "arm")]
+    core::arch::asm!(
+        "ldr {tmp}, [{cr}]",
+        "orr {tmp}, {tmp}, {fstpg}",
+        "str {tmp}, [{cr}]",
+        // The double-words are written as two words, the lower one first
+        "2:",
+        "ldr {tmp}, [{row}], #4",
+        "str {tmp}, [{address}], #4",
+        "subs {words}, {words}, #1",
+        "bne 2b",
+        "3:",
+        "ldr {tmp}, [{sr}]",
+        "tst {tmp}, {bsy}",
+        "bne 3b",
+        "ldr {tmp}, [{cr}]",
+        "bic {tmp}, {tmp}, {fstpg}",
+        "str {tmp}, [{cr}]",
+        cr = in(reg) cr,
+        sr = in(reg) sr,
+        fstpg = in(reg) CR_FSTPG,
+        bsy = in(reg) SR_BSY,
+        address = inout(reg) address => _,
+        row = inout(reg) row as *const _ as *const u32 => _,
+        words = inout(reg) ROW_DWORDS * 2 => _,
+        tmp = out(reg) _,
+        options(nostack),
+    );
+
+    #[cfg(not(target_arch = "arm"))]
+    {
+        let _ = (cr, sr, address, row, CR_FSTPG, SR_BSY);
+        unimplemented!("fast programming only runs on the device");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_areas() {
+        let geometry = Geometry {
+            size: 1024 * 1024,
+            dual_bank: true,
+        };
+
+        assert_eq!(
+            WrpArea::covering(&geometry, 0x0800_0000..0x0800_4000, false),
+            Some((Bank::Bank1, WrpArea { start: 0, end: 7 }))
+        );
+        assert_eq!(
+            WrpArea::covering(&geometry, 0x0808_0800..0x0808_0801, false),
+            Some((Bank::Bank2, WrpArea { start: 1, end: 1 }))
+        );
+        // Bank 2 is mapped at 0x0800_0000 when the banks are swapped
+        assert_eq!(
+            WrpArea::covering(&geometry, 0x0800_0800..0x0800_1000, true),
+            Some((Bank::Bank2, WrpArea { start: 1, end: 1 }))
+        );
+        assert_eq!(
+            PcropArea::covering(&geometry, 0x0808_0010..0x0808_0101, false),
+            Some((Bank::Bank2, PcropArea { start: 2, end: 32 }))
+        );
+
+        // Across the banks, outside of the flash memory or empty
+        assert_eq!(
+            WrpArea::covering(&geometry, 0x0807_F800..0x0808_0800, false),
+            None
+        );
+        assert_eq!(
+            WrpArea::covering(&geometry, 0x080F_F800..0x0810_0800, false),
+            None
+        );
+        assert_eq!(
+            PcropArea::covering(&geometry, 0x0800_0000..0x0800_0000, false),
+            None
+        );
+
+        let single = Geometry {
+            size: 256 * 1024,
+            dual_bank: false,
+        };
+        assert_eq!(
+            WrpArea::covering(&single, 0x0803_F800..0x0804_0000, true),
+            Some((
+                Bank::Bank1,
+                WrpArea {
+                    start: 127,
+                    end: 127
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn slice_bounds() {
+        assert!(check_slice(0, 4096, 8, 4096).is_ok());
+        assert!(check_slice(4088, 8, 8, 4096).is_ok());
+        assert!(check_slice(4096, 0, 8, 4096).is_ok());
+        assert!(matches!(
+            check_slice(4096, 8, 8, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+        assert!(matches!(
+            check_slice(0, 4104, 8, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+        assert!(matches!(
+            check_slice(u32::MAX, 8, 8, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn slice_alignment() {
+        assert!(check_slice(3, 5, 1, 4096).is_ok());
+        assert!(matches!(
+            check_slice(4, 8, 8, 4096),
+            Err(RegionError::NotAligned)
+        ));
+        assert!(matches!(
+            check_slice(8, 12, 8, 4096),
+            Err(RegionError::NotAligned)
+        ));
+        // Bounds are checked first
+        assert!(matches!(
+            check_slice(4092, 8, 8, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn erase_range() {
+        assert!(check_erase(0, 4096, 2048, 4096).is_ok());
+        assert!(check_erase(2048, 2048, 2048, 4096).is_ok());
+        assert!(matches!(
+            check_erase(2048, 0, 2048, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+        assert!(matches!(
+            check_erase(0, 6144, 2048, 4096),
+            Err(RegionError::OutOfBounds)
+        ));
+        assert!(matches!(
+            check_erase(8, 2048, 2048, 4096),
+            Err(RegionError::NotAligned)
+        ));
+        assert!(matches!(
+            check_erase(0, 2056, 2048, 4096),
+            Err(RegionError::NotAligned)
+        ));
+    }
 }
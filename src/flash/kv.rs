@@ -0,0 +1,778 @@
+//! Wear-levelled key/value store
+//!
+//! Values are appended as records to the active page of a region of two or more pages. When the
+//! active page is full, the last value of each key is copied to the next page, which becomes the
+//! active page once its header is written, so the pages are erased in rotation. Each record is
+//! protected by a CRC and its header is written after its value: after a power failure, a key
+//! holds either its previous or its new value.
+//!
+//! The store works on any `embedded-storage` [`NorFlash`], such as a
+//! [`FlashRegion`](super::FlashRegion) of the internal flash memory, and computes the CRCs with a
+//! [`Checksum`], such as the [`Crc`] peripheral.
+//!
+//! On the internal flash memory, the double-words left half-programmed by a power failure may hold
+//! an uncorrectable ECC error. Mounting the store reads them back, which raises an NMI instead of
+//! returning their content. The NMI handler must then clear ECCD in FLASH_ECCR (write 1) and
+//! return, so that the read completes and the store recovers the page, otherwise a power failure
+//! during a write leaves a store that cannot be mounted.
+//!
+//! ```
+//! let mut prog = flash.keyr.unlock_flash(&mut flash.sr, &mut flash.cr)?;
+//! let region = FlashRegion::new(&mut prog, 252..256)?;
+//! let mut store = Store::new(region, crc)?;
+//!
+//! store.write(CALIBRATION, &calibration)?;
+//! let len = store.read(CALIBRATION, &mut buffer)?;
+//! ```
+
+use crate::crc::Crc;
+use embedded_storage::nor_flash::NorFlash;
+
+/// Size of the page and record headers, records are aligned on it
+const DWORD: usize = 8;
+
+/// XORed with the sequence number of a page in its header
+const PAGE_MAGIC: u32 = 0x4B56_5354;
+
+/// Length flag of the record of a removed key
+const REMOVED: u16 = 0x8000;
+
+/// Key of an erased record header
+const ERASED_KEY: u16 = 0xFFFF;
+
+/// Checksum of the records
+pub trait Checksum {
+    /// Starts a new checksum
+    fn reset(&mut self);
+
+    /// Adds `data` to the checksum
+    fn feed(&mut self, data: &[u8]);
+
+    /// Returns the checksum
+    fn result(&mut self) -> u32;
+}
+
+impl Checksum for Crc {
+    fn reset(&mut self) {
+        Crc::reset(self);
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        Crc::feed(self, data);
+    }
+
+    fn result(&mut self) -> u32 {
+        Crc::result(self)
+    }
+}
+
+/// Store error
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error<E> {
+    /// The flash memory reported an error
+    Flash(E),
+    /// The region has less than two pages, or the flash memory cannot read and write 8 bytes
+    InvalidRegion,
+    /// The key 0xFFFF is reserved
+    InvalidKey,
+    /// The value does not fit in a page
+    TooLarge,
+    /// The buffer is smaller than the value
+    BufferTooSmall,
+    /// The last values of the keys do not fit in a page
+    Full,
+}
+
+/// Record of the active page, with a valid CRC
+#[derive(Copy, Clone, Debug)]
+struct Record {
+    offset: usize,
+    key: u16,
+    len: usize,
+    removed: bool,
+}
+
+impl Record {
+    fn size(&self) -> usize {
+        DWORD + align(self.len)
+    }
+}
+
+enum Scan {
+    Record(Record),
+    End,
+    Corrupted,
+}
+
+fn align(len: usize) -> usize {
+    len.div_ceil(DWORD) * DWORD
+}
+
+/// Key/value store on the pages of a NOR flash
+pub struct Store<F, C> {
+    flash: F,
+    crc: C,
+    pages: usize,
+    active: usize,
+    sequence: u32,
+    /// Offset of the free space of the active page
+    end: usize,
+}
+
+impl<F, C> Store<F, C>
+where
+    F: NorFlash,
+    C: Checksum,
+{
+    /// Mounts the store, the region is formatted if it holds no store
+    ///
+    /// On the internal flash memory, this needs the NMI handler described in the module
+    /// documentation to recover from a power failure.
+    pub fn new(flash: F, crc: C) -> Result<Self, Error<F::Error>> {
+        let pages = flash.capacity() / F::ERASE_SIZE;
+
+        if pages < 2
+            || !DWORD.is_multiple_of(F::READ_SIZE)
+            || !DWORD.is_multiple_of(F::WRITE_SIZE)
+            || !F::ERASE_SIZE.is_multiple_of(DWORD)
+        {
+            return Err(Error::InvalidRegion);
+        }
+
+        let mut store = Store {
+            flash,
+            crc,
+            pages,
+            active: 0,
+            sequence: 0,
+            end: DWORD,
+        };
+
+        let mut found = false;
+        for page in 0..pages {
+            if let Some(sequence) = store.page_sequence(page)? {
+                if !found || sequence.wrapping_sub(store.sequence) as i32 > 0 {
+                    store.active = page;
+                    store.sequence = sequence;
+                    found = true;
+                }
+            }
+        }
+
+        if found {
+            store.end = store.find_end()?;
+        } else {
+            store.erase(0)?;
+            store.write_header(0, 0)?;
+        }
+
+        Ok(store)
+    }
+
+    /// Reads the value of `key` into `buffer`, returns its length or `None` if the key has no
+    /// value
+    pub fn read(&mut self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+
+        let record = match self.find(key)? {
+            Some(record) if !record.removed => record,
+            _ => return Ok(None),
+        };
+
+        if buffer.len() < record.len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        for (i, chunk) in buffer[..record.len].chunks_mut(DWORD).enumerate() {
+            let dword = self.read_dword(self.active, record.offset + DWORD + i * DWORD)?;
+            chunk.copy_from_slice(&dword[..chunk.len()]);
+        }
+
+        Ok(Some(record.len))
+    }
+
+    /// Writes the value of `key`
+    pub fn write(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+        self.store(key, Some(value))
+    }
+
+    /// Removes the value of `key`
+    pub fn remove(&mut self, key: u16) -> Result<(), Error<F::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+
+        match self.find(key)? {
+            Some(record) if !record.removed => self.store(key, None),
+            _ => Ok(()),
+        }
+    }
+
+    /// Releases the flash memory and the checksum
+    pub fn free(self) -> (F, C) {
+        (self.flash, self.crc)
+    }
+
+    fn store(&mut self, key: u16, value: Option<&[u8]>) -> Result<(), Error<F::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+
+        let len = value.map_or(0, |value| value.len());
+        let size = DWORD + align(len);
+
+        if len >= REMOVED as usize || DWORD + size > F::ERASE_SIZE {
+            return Err(Error::TooLarge);
+        }
+
+        if self.end + size <= F::ERASE_SIZE {
+            self.append(self.active, self.end, key, value)?;
+            self.end += size;
+
+            Ok(())
+        } else {
+            self.collect(key, value)
+        }
+    }
+
+    /// Copies the last values of the other keys and the value of `key` to the next page, which
+    /// becomes the active page
+    fn collect(&mut self, key: u16, value: Option<&[u8]>) -> Result<(), Error<F::Error>> {
+        let target = (self.active + 1) % self.pages;
+
+        if !self.is_erased(target, 0)? {
+            self.erase(target)?;
+        }
+
+        let mut end = DWORD;
+        let mut offset = DWORD;
+        while offset < self.end {
+            let record = match self.scan(self.active, offset)? {
+                Scan::Record(record) => record,
+                _ => break,
+            };
+            offset += record.size();
+
+            if record.key == key || record.removed || self.superseded(&record)? {
+                continue;
+            }
+
+            if end + record.size() > F::ERASE_SIZE {
+                return Err(Error::Full);
+            }
+
+            self.copy(&record, target, end)?;
+            end += record.size();
+        }
+
+        if let Some(value) = value {
+            let size = DWORD + align(value.len());
+
+            if end + size > F::ERASE_SIZE {
+                return Err(Error::Full);
+            }
+
+            self.append(target, end, key, Some(value))?;
+            end += size;
+        }
+
+        // The previous active page stays valid until the header is written
+        let sequence = self.sequence.wrapping_add(1);
+        self.write_header(target, sequence)?;
+
+        self.active = target;
+        self.sequence = sequence;
+        self.end = end;
+
+        Ok(())
+    }
+
+    /// Last record of `key` in the active page
+    fn find(&mut self, key: u16) -> Result<Option<Record>, Error<F::Error>> {
+        let mut found = None;
+        let mut offset = DWORD;
+
+        while offset < self.end {
+            let record = match self.scan(self.active, offset)? {
+                Scan::Record(record) => record,
+                _ => break,
+            };
+
+            if record.key == key {
+                found = Some(record);
+            }
+            offset += record.size();
+        }
+
+        Ok(found)
+    }
+
+    /// `record` is followed by another record of its key in the active page
+    fn superseded(&mut self, record: &Record) -> Result<bool, Error<F::Error>> {
+        let mut offset = record.offset + record.size();
+
+        while offset < self.end {
+            match self.scan(self.active, offset)? {
+                Scan::Record(next) if next.key == record.key => return Ok(true),
+                Scan::Record(next) => offset += next.size(),
+                _ => break,
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Offset of the free space of the active page, or the end of the page if the page holds a
+    /// corrupted record or a write was interrupted
+    fn find_end(&mut self) -> Result<usize, Error<F::Error>> {
+        let mut offset = DWORD;
+
+        loop {
+            match self.scan(self.active, offset)? {
+                Scan::Record(record) => offset += record.size(),
+                Scan::End => break,
+                Scan::Corrupted => return Ok(F::ERASE_SIZE),
+            }
+        }
+
+        // Words programmed by an interrupted write cannot be programmed again. Reading them may
+        // raise an ECC error NMI on the internal flash memory, see the module documentation.
+        if self.is_erased(self.active, offset)? {
+            Ok(offset)
+        } else {
+            Ok(F::ERASE_SIZE)
+        }
+    }
+
+    fn scan(&mut self, page: usize, offset: usize) -> Result<Scan, Error<F::Error>> {
+        if offset + DWORD > F::ERASE_SIZE {
+            return Ok(Scan::End);
+        }
+
+        let header = self.read_dword(page, offset)?;
+        if header == [0xFF; DWORD] {
+            return Ok(Scan::End);
+        }
+
+        let key = u16::from_le_bytes([header[0], header[1]]);
+        let raw_len = u16::from_le_bytes([header[2], header[3]]);
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let len = (raw_len & !REMOVED) as usize;
+
+        if key == ERASED_KEY || offset + DWORD + align(len) > F::ERASE_SIZE {
+            return Ok(Scan::Corrupted);
+        }
+
+        self.crc.reset();
+        self.crc.feed(&header[..4]);
+        for start in (0..len).step_by(DWORD) {
+            let dword = self.read_dword(page, offset + DWORD + start)?;
+            let n = core::cmp::min(DWORD, len - start);
+            self.crc.feed(&dword[..n]);
+        }
+
+        if self.crc.result() != crc {
+            return Ok(Scan::Corrupted);
+        }
+
+        Ok(Scan::Record(Record {
+            offset,
+            key,
+            len,
+            removed: raw_len & REMOVED != 0,
+        }))
+    }
+
+    fn append(
+        &mut self,
+        page: usize,
+        offset: usize,
+        key: u16,
+        value: Option<&[u8]>,
+    ) -> Result<(), Error<F::Error>> {
+        let (raw_len, value) = match value {
+            Some(value) => (value.len() as u16, value),
+            None => (REMOVED, &[][..]),
+        };
+
+        self.crc.reset();
+        self.crc.feed(&key.to_le_bytes());
+        self.crc.feed(&raw_len.to_le_bytes());
+        self.crc.feed(value);
+        let crc = self.crc.result();
+
+        for (i, chunk) in value.chunks(DWORD).enumerate() {
+            let mut dword = [0xFF; DWORD];
+            dword[..chunk.len()].copy_from_slice(chunk);
+            self.write_dword(page, offset + DWORD + i * DWORD, &dword)?;
+        }
+
+        // The record is valid once its header is written
+        let mut header = [0; DWORD];
+        header[..2].copy_from_slice(&key.to_le_bytes());
+        header[2..4].copy_from_slice(&raw_len.to_le_bytes());
+        header[4..].copy_from_slice(&crc.to_le_bytes());
+        self.write_dword(page, offset, &header)
+    }
+
+    fn copy(&mut self, record: &Record, page: usize, offset: usize) -> Result<(), Error<F::Error>> {
+        for start in (DWORD..record.size()).step_by(DWORD) {
+            let dword = self.read_dword(self.active, record.offset + start)?;
+            self.write_dword(page, offset + start, &dword)?;
+        }
+
+        let header = self.read_dword(self.active, record.offset)?;
+        self.write_dword(page, offset, &header)
+    }
+
+    fn page_sequence(&mut self, page: usize) -> Result<Option<u32>, Error<F::Error>> {
+        let header = self.read_dword(page, 0)?;
+        let sequence = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let check = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        if check == sequence ^ PAGE_MAGIC {
+            Ok(Some(sequence))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write_header(&mut self, page: usize, sequence: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0; DWORD];
+        header[..4].copy_from_slice(&sequence.to_le_bytes());
+        header[4..].copy_from_slice(&(sequence ^ PAGE_MAGIC).to_le_bytes());
+        self.write_dword(page, 0, &header)
+    }
+
+    fn is_erased(&mut self, page: usize, from: usize) -> Result<bool, Error<F::Error>> {
+        for offset in (from..F::ERASE_SIZE).step_by(DWORD) {
+            if self.read_dword(page, offset)? != [0xFF; DWORD] {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Error<F::Error>> {
+        let from = (page * F::ERASE_SIZE) as u32;
+        self.flash
+            .erase(from, from + F::ERASE_SIZE as u32)
+            .map_err(Error::Flash)
+    }
+
+    fn read_dword(&mut self, page: usize, offset: usize) -> Result<[u8; DWORD], Error<F::Error>> {
+        let mut dword = [0; DWORD];
+        self.flash
+            .read((page * F::ERASE_SIZE + offset) as u32, &mut dword)
+            .map_err(Error::Flash)?;
+
+        Ok(dword)
+    }
+
+    fn write_dword(
+        &mut self,
+        page: usize,
+        offset: usize,
+        dword: &[u8; DWORD],
+    ) -> Result<(), Error<F::Error>> {
+        self.flash
+            .write((page * F::ERASE_SIZE + offset) as u32, dword)
+            .map_err(Error::Flash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checksum, Error, Store};
+    use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+    const PAGE: usize = 256;
+    const PAGES: usize = 4;
+
+    /// NOR flash in RAM, which loses power after `budget` writes or erases
+    #[derive(Clone)]
+    struct Ram {
+        memory: [u8; PAGE * PAGES],
+        erases: [usize; PAGES],
+        budget: Option<usize>,
+    }
+
+    impl Ram {
+        fn new() -> Self {
+            Ram {
+                memory: [0xFF; PAGE * PAGES],
+                erases: [0; PAGES],
+                budget: None,
+            }
+        }
+
+        /// Copy of the memory that loses power after `budget` writes or erases
+        fn with_budget(&self, budget: usize) -> Self {
+            Ram {
+                budget: Some(budget),
+                ..self.clone()
+            }
+        }
+
+        fn power_lost(&mut self) -> bool {
+            match self.budget {
+                Some(0) => true,
+                Some(ref mut budget) => {
+                    *budget -= 1;
+                    false
+                }
+                None => false,
+            }
+        }
+    }
+
+    impl ErrorType for Ram {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for Ram {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.memory[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            PAGE * PAGES
+        }
+    }
+
+    impl NorFlash for Ram {
+        const WRITE_SIZE: usize = 8;
+        const ERASE_SIZE: usize = PAGE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let (from, to) = (from as usize, to as usize);
+            assert!(from % PAGE == 0 && to % PAGE == 0);
+
+            if self.power_lost() {
+                // Half of the page is erased
+                let half = from + PAGE / 2;
+                self.memory[from..half].iter_mut().for_each(|b| *b = 0xFF);
+                return Err(NorFlashErrorKind::Other);
+            }
+
+            self.memory[from..to].iter_mut().for_each(|b| *b = 0xFF);
+            for page in from / PAGE..to / PAGE {
+                self.erases[page] += 1;
+            }
+
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            assert!(offset.is_multiple_of(8) && bytes.len().is_multiple_of(8));
+            assert!(
+                self.memory[offset..offset + bytes.len()]
+                    .iter()
+                    .all(|b| *b == 0xFF),
+                "programmed twice at {:#x}",
+                offset
+            );
+
+            if self.power_lost() {
+                // Half of the first word is programmed
+                self.memory[offset..offset + 4].copy_from_slice(&bytes[..4]);
+                return Err(NorFlashErrorKind::Other);
+            }
+
+            self.memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+            Ok(())
+        }
+    }
+
+    /// CRC-32 (IEEE 802.3)
+    struct Crc32(u32);
+
+    impl Checksum for Crc32 {
+        fn reset(&mut self) {
+            self.0 = 0xFFFF_FFFF;
+        }
+
+        fn feed(&mut self, data: &[u8]) {
+            for byte in data {
+                self.0 ^= *byte as u32;
+                for _ in 0..8 {
+                    self.0 = if self.0 & 1 != 0 {
+                        (self.0 >> 1) ^ 0xEDB8_8320
+                    } else {
+                        self.0 >> 1
+                    };
+                }
+            }
+        }
+
+        fn result(&mut self) -> u32 {
+            !self.0
+        }
+    }
+
+    fn mount(ram: Ram) -> Store<Ram, Crc32> {
+        Store::new(ram, Crc32(0)).unwrap()
+    }
+
+    fn value(store: &mut Store<Ram, Crc32>, key: u16) -> Option<([u8; 64], usize)> {
+        let mut buffer = [0; 64];
+        store
+            .read(key, &mut buffer)
+            .unwrap()
+            .map(|len| (buffer, len))
+    }
+
+    fn assert_value(store: &mut Store<Ram, Crc32>, key: u16, expected: &[u8]) {
+        let (buffer, len) = value(store, key).expect("no value");
+        assert_eq!(&buffer[..len], expected);
+    }
+
+    #[test]
+    fn write_read_remove() {
+        let mut store = mount(Ram::new());
+
+        assert_eq!(value(&mut store, 1), None);
+        store.write(1, b"calibration").unwrap();
+        store.write(2, &[]).unwrap();
+        store.write(3, &[0xAA; 17]).unwrap();
+        store.write(1, b"offset").unwrap();
+
+        assert_value(&mut store, 1, b"offset");
+        assert_value(&mut store, 2, &[]);
+        assert_value(&mut store, 3, &[0xAA; 17]);
+
+        store.remove(3).unwrap();
+        store.remove(4).unwrap();
+        assert_eq!(value(&mut store, 3), None);
+
+        let mut small = [0; 4];
+        assert_eq!(store.read(1, &mut small), Err(Error::BufferTooSmall));
+        assert_eq!(store.write(0xFFFF, &[]), Err(Error::InvalidKey));
+        assert_eq!(store.write(5, &[0; PAGE]), Err(Error::TooLarge));
+
+        let (ram, _) = store.free();
+        let mut store = mount(ram);
+        assert_value(&mut store, 1, b"offset");
+        assert_value(&mut store, 2, &[]);
+        assert_eq!(value(&mut store, 3), None);
+    }
+
+    #[test]
+    fn rotation() {
+        let mut store = mount(Ram::new());
+
+        store.write(10, b"serial number").unwrap();
+        for i in 0..200u32 {
+            store.write(1, &i.to_le_bytes()).unwrap();
+            store.write(2, &[i as u8; 20]).unwrap();
+        }
+
+        assert_value(&mut store, 10, b"serial number");
+        assert_value(&mut store, 1, &199u32.to_le_bytes());
+
+        let (ram, _) = store.free();
+        // All the pages are used, the erases are spread over them (the first page is also erased
+        // when the region is formatted)
+        let min = *ram.erases.iter().min().unwrap();
+        let max = *ram.erases.iter().max().unwrap();
+        assert!(min > 0 && max - min <= 2, "{:?}", ram.erases);
+
+        let mut store = mount(ram);
+        assert_value(&mut store, 10, b"serial number");
+        assert_value(&mut store, 2, &[199; 20]);
+    }
+
+    #[test]
+    fn full() {
+        let mut store = mount(Ram::new());
+
+        let mut key = 0;
+        let result = loop {
+            if let Err(error) = store.write(key, &[key as u8; 48]) {
+                break error;
+            }
+            key += 1;
+        };
+        assert_eq!(result, Error::Full);
+
+        // The values written before are kept, and can still be updated
+        for k in 0..key {
+            assert_value(&mut store, k, &[k as u8; 48]);
+        }
+        store.write(0, &[0x55; 48]).unwrap();
+        assert_value(&mut store, 0, &[0x55; 48]);
+        assert_value(&mut store, key - 1, &[(key - 1) as u8; 48]);
+    }
+
+    #[test]
+    fn power_failure() {
+        let mut store = mount(Ram::new());
+        store.write(100, b"kept").unwrap();
+
+        for step in 0..40u8 {
+            let key = 1 + (step % 3) as u16;
+            let new = [step; 27];
+
+            let (ram, crc) = store.free();
+            let old = {
+                let mut store = mount(ram.clone());
+                value(&mut store, key)
+            };
+
+            for budget in 0..40 {
+                let mut interrupted = mount(ram.with_budget(budget));
+                let result = interrupted.write(key, &new);
+
+                let (mut ram, _) = interrupted.free();
+                ram.budget = None;
+                let mut store = mount(ram);
+
+                let current = value(&mut store, key);
+                let current = current.as_ref().map(|(buffer, len)| &buffer[..*len]);
+                if result.is_ok() {
+                    assert_eq!(current, Some(&new[..]));
+                } else {
+                    let old = old.as_ref().map(|(buffer, len)| &buffer[..*len]);
+                    assert!(current == old || current == Some(&new[..]));
+                }
+                assert_value(&mut store, 100, b"kept");
+
+                // The store can still be written after the power failure
+                store.write(key, &new).unwrap();
+                assert_value(&mut store, key, &new);
+            }
+
+            store = Store::new(ram, crc).unwrap();
+            store.write(key, &new).unwrap();
+        }
+    }
+
+    #[test]
+    fn corrupted_record() {
+        let mut store = mount(Ram::new());
+        store.write(1, b"first").unwrap();
+        store.write(1, b"second").unwrap();
+
+        let (mut ram, _) = store.free();
+        // Value of the second record of the first page
+        let page = (0..PAGES)
+            .find(|page| ram.memory[page * PAGE] != 0xFF)
+            .unwrap();
+        ram.memory[page * PAGE + 32] = 0;
+
+        let mut store = mount(ram);
+        assert_value(&mut store, 1, b"first");
+
+        store.write(1, b"third").unwrap();
+        assert_value(&mut store, 1, b"third");
+    }
+}
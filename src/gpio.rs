@@ -163,6 +163,61 @@ macro_rules! impl_into_af {
     }
 }
 
+macro_rules! impl_exti_pin {
+    ($PXi:ident, $i:expr, $exticri:ident, $extigpionr:expr, <$($GEN:ident),*>, $MODE:ty) => {
+        impl<$($GEN),*> ExtiPin for $PXi<$MODE> {
+            /// Configure EXTI Line $i to trigger from this pin.
+            fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG, apb2: &mut APB2) {
+                apb2.enr().modify(|_,w| w.syscfgen().set_bit());
+                let offset = 4 * ($i % 4);
+                syscfg.$exticri.modify(|r, w| unsafe {
+                    let mut exticr = r.bits();
+                    exticr = (exticr & !(0xf << offset)) | ($extigpionr << offset);
+                    w.bits(exticr)
+                });
+            }
+
+            /// Generate interrupt on rising edge, falling edge or both
+            fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
+                match edge {
+                    Edge::Rising => {
+                        exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+                        exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                    },
+                    Edge::Falling => {
+                        exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+                        exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                    },
+                    Edge::RisingFalling => {
+                        exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+                        exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+                    }
+                }
+            }
+
+            /// Enable external interrupts from this pin.
+            fn enable_interrupt(&mut self, exti: &mut EXTI) {
+                exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+            }
+
+            /// Disable external interrupts from this pin
+            fn disable_interrupt(&mut self, exti: &mut EXTI) {
+                exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+            }
+
+            /// Clear the interrupt pending bit for this pin
+            fn clear_interrupt_pending_bit(&mut self) {
+                unsafe { (*EXTI::ptr()).pr1.write(|w| w.bits(1 << $i) ) };
+            }
+
+            /// Reads the interrupt pending bit for this pin
+            fn check_interrupt(&mut self) -> bool {
+                unsafe { ((*EXTI::ptr()).pr1.read().bits() & (1 << $i)) != 0 }
+            }
+        }
+    };
+}
+
 // In general, each parameter should use the same identifying letter. The third parameter, $gpioy,
 // is an exception: it refers to the path to the RegisterBlock trait, which is sometimes reused. To
 // find out which $gpioy to use, search in the stm32l4 documentation for the GPIOX struct, click on
@@ -694,57 +749,22 @@ macro_rules! gpio {
                     }
                 }
 
-                impl<MODE> ExtiPin for $PXi<Input<MODE>> {
-                    /// Configure EXTI Line $i to trigger from this pin.
-                    fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG, apb2: &mut APB2) {
-                        apb2.enr().modify(|_,w| w.syscfgen().set_bit());
-                        let offset = 4 * ($i % 4);
-                        syscfg.$exticri.modify(|r, w| unsafe {
-                            let mut exticr = r.bits();
-                            exticr = (exticr & !(0xf << offset)) | ($extigpionr << offset);
-                            w.bits(exticr)
-                        });
-                    }
-
-                    /// Generate interrupt on rising edge, falling edge or both
-                    fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
-                        match edge {
-                            Edge::Rising => {
-                                exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
-                                exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
-                            },
-                            Edge::Falling => {
-                                exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
-                                exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
-                            },
-                            Edge::RisingFalling => {
-                                exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
-                                exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
-                            }
-                        }
-                    }
-
-                    /// Enable external interrupts from this pin.
-                    fn enable_interrupt(&mut self, exti: &mut EXTI) {
-                        exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
-                    }
-
-                    /// Disable external interrupts from this pin
-                    fn disable_interrupt(&mut self, exti: &mut EXTI) {
-                        exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
-                    }
+                impl InputPin for $PXi<Output<OpenDrain>> {
+                    type Error = Infallible;
 
-                    /// Clear the interrupt pending bit for this pin
-                    fn clear_interrupt_pending_bit(&mut self) {
-                        unsafe { (*EXTI::ptr()).pr1.write(|w| w.bits(1 << $i) ) };
+                    fn is_high(&self) -> Result<bool, Self::Error> {
+                        Ok(!self.is_low().unwrap())
                     }
 
-                    /// Reads the interrupt pending bit for this pin
-                    fn check_interrupt(&mut self) -> bool {
-                        unsafe { ((*EXTI::ptr()).pr1.read().bits() & (1 << $i)) != 0 }
+                    fn is_low(&self) -> Result<bool, Self::Error> {
+                        // NOTE(unsafe) atomic read with no side effects
+                        Ok(unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << $i) == 0 })
                     }
                 }
 
+                impl_exti_pin!($PXi, $i, $exticri, $extigpionr, <MODE>, Input<MODE>);
+                impl_exti_pin!($PXi, $i, $exticri, $extigpionr, <AF, MODE>, Alternate<AF, Input<MODE>>);
+
                 impl<MODE> $PXi<MODE> {
                     impl_into_af! {
                         $PXi $AFR $i,
@@ -102,6 +102,14 @@ pub mod dma;
     feature = "stm32l4x5",
     feature = "stm32l4x6"
 ))]
+pub mod firewall;
+#[cfg(any(
+    feature = "stm32l4x1",
+    feature = "stm32l4x2",
+    feature = "stm32l4x3",
+    feature = "stm32l4x5",
+    feature = "stm32l4x6"
+))]
 pub mod flash;
 #[cfg(any(
     feature = "stm32l4x1",
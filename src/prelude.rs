@@ -6,6 +6,7 @@ pub use crate::hal::prelude::*; // embedded hal traits // for some reason v2 is
 pub use crate::crc::CrcExt as _stm32l4_hal_CrcExt;
 pub use crate::datetime::U32Ext as _stm32l4_hal_datetime_U32Ext;
 pub use crate::dma::DmaExt as _stm32l4_hal_DmaExt;
+pub use crate::firewall::FirewallExt as _stm32l4_hal_FirewallExt;
 pub use crate::flash::FlashExt as _stm32l4_hal_FlashExt;
 pub use crate::gpio::ExtiPin as _stm32l4_hal_ExtiPin;
 pub use crate::gpio::GpioExt as _stm32l4_hal_GpioExt;
@@ -23,6 +23,9 @@ use crate::gpio::{
     gpiod::{PD3, PD4, PD5, PD6, PD7},
 };
 
+#[cfg(feature = "stm32l4x5")]
+use crate::gpio::gpiod::{PD3, PD4, PD5, PD6, PD7};
+
 #[cfg(feature = "stm32l4x2")]
 use crate::gpio::gpiob::PB2;
 
@@ -32,10 +35,18 @@ use crate::gpio::{
     gpiof::{PF6, PF7, PF8, PF9},
 };
 
+use crate::dma::{self, dma1, dma2, TransferPayload};
 use crate::gpio::{Alternate, Floating, Input, Speed, AF10};
 use crate::rcc::AHB3;
 use crate::stm32::QUADSPI;
-use core::ptr;
+use core::sync::atomic::{self, Ordering};
+use core::{ptr, slice};
+use embedded_dma::{StaticReadBuffer, StaticWriteBuffer};
+
+pub mod nor;
+
+/// Start of the memory region mapped to the flash in memory-mapped mode
+pub const MEMORY_MAPPED_BASE: usize = 0x9000_0000;
 
 #[doc(hidden)]
 mod private {
@@ -66,6 +77,26 @@ pub trait IO2Pin<QSPI>: private::Sealed {
 pub trait IO3Pin<QSPI>: private::Sealed {
     fn set_speed(self, speed: Speed) -> Self;
 }
+/// Bank 2 nCS pin. This trait is sealed and cannot be implemented.
+pub trait BK2NCSPin<QSPI>: private::Sealed {
+    fn set_speed(self, speed: Speed) -> Self;
+}
+/// Bank 2 IO0 pin. This trait is sealed and cannot be implemented.
+pub trait BK2IO0Pin<QSPI>: private::Sealed {
+    fn set_speed(self, speed: Speed) -> Self;
+}
+/// Bank 2 IO1 pin. This trait is sealed and cannot be implemented.
+pub trait BK2IO1Pin<QSPI>: private::Sealed {
+    fn set_speed(self, speed: Speed) -> Self;
+}
+/// Bank 2 IO2 pin. This trait is sealed and cannot be implemented.
+pub trait BK2IO2Pin<QSPI>: private::Sealed {
+    fn set_speed(self, speed: Speed) -> Self;
+}
+/// Bank 2 IO3 pin. This trait is sealed and cannot be implemented.
+pub trait BK2IO3Pin<QSPI>: private::Sealed {
+    fn set_speed(self, speed: Speed) -> Self;
+}
 
 macro_rules! pins {
     ($qspi:ident, $af:ident, CLK: [$($clk:ident),*], nCS: [$($ncs:ident),*],
@@ -119,12 +150,59 @@ macro_rules! pins {
                 }
             }
         )*
-    }
+    };
+    // The bank 2 pins are bank 1 pins on the single bank devices sharing a feature, so `Sealed`
+    // is implemented separately
+    ($qspi:ident, $af:ident, BK2_nCS: [$($ncs:ident),*], BK2_IO0: [$($io0:ident),*],
+        BK2_IO1: [$($io1:ident),*], BK2_IO2: [$($io2:ident),*], BK2_IO3: [$($io3:ident),*]) => {
+        $(
+            impl BK2NCSPin<$qspi> for $ncs<Alternate<$af, Input<Floating>>> {
+                fn set_speed(self, speed: Speed) -> Self{
+                    self.set_speed(speed)
+                }
+            }
+        )*
+        $(
+            impl BK2IO0Pin<$qspi> for $io0<Alternate<$af, Input<Floating>>> {
+                fn set_speed(self, speed: Speed) -> Self{
+                    self.set_speed(speed)
+                }
+            }
+        )*
+        $(
+            impl BK2IO1Pin<$qspi> for $io1<Alternate<$af, Input<Floating>>> {
+                fn set_speed(self, speed: Speed) -> Self{
+                    self.set_speed(speed)
+                }
+            }
+        )*
+        $(
+            impl BK2IO2Pin<$qspi> for $io2<Alternate<$af, Input<Floating>>> {
+                fn set_speed(self, speed: Speed) -> Self{
+                    self.set_speed(speed)
+                }
+            }
+        )*
+        $(
+            impl BK2IO3Pin<$qspi> for $io3<Alternate<$af, Input<Floating>>> {
+                fn set_speed(self, speed: Speed) -> Self{
+                    self.set_speed(speed)
+                }
+            }
+        )*
+    };
+    ($af:ident, Sealed: [$($pin:ident),*]) => {
+        $(
+            impl private::Sealed for $pin<Alternate<$af, Input<Floating>>> {}
+        )*
+    };
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 pub enum QspiMode {
+    /// The phase is skipped
+    NoPhase = 0b00,
     SingleChannel = 0b01,
     DualChannel = 0b10,
     QuadChannel = 0b11,
@@ -139,6 +217,13 @@ pub enum AddressSize {
     Addr32Bit = 0b11,
 }
 
+/// Flash memory accessed in single-flash mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FlashBank {
+    Bank1,
+    Bank2,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SampleShift {
     None,
@@ -153,9 +238,62 @@ pub enum ClockMode {
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum QspiError {
+    /// A command is already ongoing
     Busy,
+    /// The address is out of the flash size of the configuration
     Address,
-    Unknown,
+    /// The peripheral flagged a transfer error (TEF)
+    TransferError,
+    /// The status did not match within the polling budget
+    Timeout,
+    /// The length of the status is not 1 to 4 bytes
+    Length,
+}
+
+/// How the status bits selected by the mask are combined in status polling mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PollingMatchMode {
+    /// All the selected bits match
+    And,
+    /// Any of the selected bits matches
+    Or,
+}
+
+/// Automatic status polling, the status read by the command is compared with `match_value` on
+/// the bits set in `mask` until it matches
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QspiStatusPolling {
+    pub match_value: u32,
+    pub mask: u32,
+    pub match_mode: PollingMatchMode,
+    /// Number of CLK cycles between two reads of the status
+    pub interval: u16,
+}
+
+impl QspiStatusPolling {
+    /// Waits until the bits set in `mask` are equal to the ones of `match_value`
+    pub fn new(match_value: u32, mask: u32) -> Self {
+        QspiStatusPolling {
+            match_value,
+            mask,
+            match_mode: PollingMatchMode::And,
+            interval: 16,
+        }
+    }
+
+    pub fn match_mode(self, mode: PollingMatchMode) -> Self {
+        QspiStatusPolling {
+            match_mode: mode,
+            ..self
+        }
+    }
+
+    pub fn interval(self, cycles: u16) -> Self {
+        QspiStatusPolling {
+            interval: cycles,
+            ..self
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -175,6 +313,13 @@ pub struct QspiConfig {
     /// remain high between commands issued to the Flash memory.
     chip_select_high_time: u8,
     qpi_mode: bool,
+    /// Data output delayed by a quarter of CLK in double data rate commands
+    ddr_hold: bool,
+    /// Set by the constructor from the pins
+    flash_bank: FlashBank,
+    /// Both flash memories are accessed in parallel, the flash size is the sum of both, set by
+    /// the constructor from the pins
+    dual_flash: bool,
 }
 
 impl Default for QspiConfig {
@@ -188,6 +333,9 @@ impl Default for QspiConfig {
             sample_shift: SampleShift::HalfACycle,
             chip_select_high_time: 1,
             qpi_mode: false,
+            ddr_hold: false,
+            flash_bank: FlashBank::Bank1,
+            dual_flash: false,
         }
     }
 }
@@ -232,6 +380,21 @@ impl QspiConfig {
         self.qpi_mode = qpi;
         self
     }
+
+    /// Delays the data output by a quarter of CLK in double data rate commands, for flash
+    /// memories that need hold time on the data
+    pub fn ddr_hold(mut self, hold: bool) -> Self {
+        self.ddr_hold = hold;
+        self
+    }
+
+    pub fn get_flash_bank(&self) -> FlashBank {
+        self.flash_bank
+    }
+
+    pub fn is_dual_flash(&self) -> bool {
+        self.dual_flash
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -315,6 +478,85 @@ impl<'a> QspiReadCommand<'a> {
     }
 }
 
+/// Read command issued by the peripheral on each access in memory-mapped mode, the address is the
+/// offset of the access in the memory-mapped region
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QspiMemoryMappedCommand<'a> {
+    pub instruction: Option<(u8, QspiMode)>,
+    pub address_mode: QspiMode,
+    pub alternative_bytes: Option<(&'a [u8], QspiMode)>,
+    pub dummy_cycles: u8,
+    pub data_mode: QspiMode,
+    pub double_data_rate: bool,
+    /// Number of CLK cycles without access after which nCS is released to save power, `None`
+    /// keeps the flash selected (and the prefetched data valid) until the next access
+    pub timeout: Option<u16>,
+}
+
+impl<'a> QspiMemoryMappedCommand<'a> {
+    pub fn alternative_bytes(self, bytes: &'a [u8], mode: QspiMode) -> Self {
+        QspiMemoryMappedCommand {
+            alternative_bytes: Some((bytes, mode)),
+            ..self
+        }
+    }
+
+    pub fn dummy_cycles(self, n: u8) -> Self {
+        QspiMemoryMappedCommand {
+            dummy_cycles: n,
+            ..self
+        }
+    }
+
+    pub fn timeout(self, cycles: u16) -> Self {
+        QspiMemoryMappedCommand {
+            timeout: Some(cycles),
+            ..self
+        }
+    }
+}
+
+/// Phases of an indirect or status polling command
+struct Phases<'a> {
+    instruction: Option<(u8, QspiMode)>,
+    address: Option<(u32, QspiMode)>,
+    alternative_bytes: Option<(&'a [u8], QspiMode)>,
+    dummy_cycles: u8,
+    /// Number of bytes and mode of the data phase
+    data: Option<(u32, QspiMode)>,
+    double_data_rate: bool,
+}
+
+impl<'a> From<&QspiReadCommand<'a>> for Phases<'a> {
+    fn from(command: &QspiReadCommand<'a>) -> Self {
+        Phases {
+            instruction: command.instruction,
+            address: command.address,
+            alternative_bytes: command.alternative_bytes,
+            dummy_cycles: command.dummy_cycles,
+            data: if command.receive_length > 0 {
+                Some((command.receive_length, command.data_mode))
+            } else {
+                None
+            },
+            double_data_rate: command.double_data_rate,
+        }
+    }
+}
+
+impl<'a> From<&QspiWriteCommand<'a>> for Phases<'a> {
+    fn from(command: &QspiWriteCommand<'a>) -> Self {
+        Phases {
+            instruction: command.instruction,
+            address: command.address,
+            alternative_bytes: command.alternative_bytes,
+            dummy_cycles: command.dummy_cycles,
+            data: command.data.map(|(data, mode)| (data.len() as u32, mode)),
+            double_data_rate: command.double_data_rate,
+        }
+    }
+}
+
 pub struct Qspi<PINS> {
     qspi: QUADSPI,
     _pins: PINS,
@@ -322,11 +564,12 @@ pub struct Qspi<PINS> {
 }
 
 impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
+    /// Accesses the flash memory of bank 1
     pub fn new(
         qspi: QUADSPI,
         pins: (CLK, NCS, IO0, IO1, IO2, IO3),
         ahb3: &mut AHB3,
-        config: QspiConfig,
+        mut config: QspiConfig,
     ) -> Self
     where
         CLK: ClkPin<QUADSPI>,
@@ -336,6 +579,104 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
         IO2: IO2Pin<QUADSPI>,
         IO3: IO3Pin<QUADSPI>,
     {
+        // Set gpio speed
+        let high_speed_pins = (
+            pins.0.set_speed(Speed::VeryHigh),
+            pins.1.set_speed(Speed::VeryHigh),
+            pins.2.set_speed(Speed::VeryHigh),
+            pins.3.set_speed(Speed::VeryHigh),
+            pins.4.set_speed(Speed::VeryHigh),
+            pins.5.set_speed(Speed::VeryHigh),
+        );
+
+        config.flash_bank = FlashBank::Bank1;
+        config.dual_flash = false;
+        Qspi::init(qspi, high_speed_pins, ahb3, config)
+    }
+
+    /// Accesses the flash memory of bank 2
+    #[cfg(any(feature = "stm32l4x1", feature = "stm32l4x5", feature = "stm32l4x6"))]
+    pub fn new_bank2(
+        qspi: QUADSPI,
+        pins: (CLK, NCS, IO0, IO1, IO2, IO3),
+        ahb3: &mut AHB3,
+        mut config: QspiConfig,
+    ) -> Self
+    where
+        CLK: ClkPin<QUADSPI>,
+        NCS: BK2NCSPin<QUADSPI>,
+        IO0: BK2IO0Pin<QUADSPI>,
+        IO1: BK2IO1Pin<QUADSPI>,
+        IO2: BK2IO2Pin<QUADSPI>,
+        IO3: BK2IO3Pin<QUADSPI>,
+    {
+        // Set gpio speed
+        let high_speed_pins = (
+            pins.0.set_speed(Speed::VeryHigh),
+            pins.1.set_speed(Speed::VeryHigh),
+            pins.2.set_speed(Speed::VeryHigh),
+            pins.3.set_speed(Speed::VeryHigh),
+            pins.4.set_speed(Speed::VeryHigh),
+            pins.5.set_speed(Speed::VeryHigh),
+        );
+
+        config.flash_bank = FlashBank::Bank2;
+        config.dual_flash = false;
+        Qspi::init(qspi, high_speed_pins, ahb3, config)
+    }
+}
+
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x5", feature = "stm32l4x6"))]
+impl<CLK, NCS, IO0, IO1, IO2, IO3, BK2IO0, BK2IO1, BK2IO2, BK2IO3>
+    Qspi<(CLK, NCS, IO0, IO1, IO2, IO3, BK2IO0, BK2IO1, BK2IO2, BK2IO3)>
+{
+    /// Accesses the flash memories of both banks in parallel (dual-flash mode), each byte is
+    /// split between the flash memories, so the throughput is doubled
+    ///
+    /// Both flash memories are selected by the nCS pin of bank 1 and receive the same commands.
+    /// `config.flash_size` is the sum of the sizes of both flash memories, and commands read or
+    /// write an even number of bytes at even addresses.
+    #[allow(clippy::type_complexity)]
+    pub fn new_dual_flash(
+        qspi: QUADSPI,
+        pins: (CLK, NCS, IO0, IO1, IO2, IO3, BK2IO0, BK2IO1, BK2IO2, BK2IO3),
+        ahb3: &mut AHB3,
+        mut config: QspiConfig,
+    ) -> Self
+    where
+        CLK: ClkPin<QUADSPI>,
+        NCS: NCSPin<QUADSPI>,
+        IO0: IO0Pin<QUADSPI>,
+        IO1: IO1Pin<QUADSPI>,
+        IO2: IO2Pin<QUADSPI>,
+        IO3: IO3Pin<QUADSPI>,
+        BK2IO0: BK2IO0Pin<QUADSPI>,
+        BK2IO1: BK2IO1Pin<QUADSPI>,
+        BK2IO2: BK2IO2Pin<QUADSPI>,
+        BK2IO3: BK2IO3Pin<QUADSPI>,
+    {
+        // Set gpio speed
+        let high_speed_pins = (
+            pins.0.set_speed(Speed::VeryHigh),
+            pins.1.set_speed(Speed::VeryHigh),
+            pins.2.set_speed(Speed::VeryHigh),
+            pins.3.set_speed(Speed::VeryHigh),
+            pins.4.set_speed(Speed::VeryHigh),
+            pins.5.set_speed(Speed::VeryHigh),
+            pins.6.set_speed(Speed::VeryHigh),
+            pins.7.set_speed(Speed::VeryHigh),
+            pins.8.set_speed(Speed::VeryHigh),
+            pins.9.set_speed(Speed::VeryHigh),
+        );
+
+        config.flash_bank = FlashBank::Bank1;
+        config.dual_flash = true;
+        Qspi::init(qspi, high_speed_pins, ahb3, config)
+    }
+}
+
+impl<PINS> Qspi<PINS> {
+    fn init(qspi: QUADSPI, pins: PINS, ahb3: &mut AHB3, config: QspiConfig) -> Self {
         // Enable quad SPI in the clocks.
         ahb3.enr().modify(|_, w| w.qspien().bit(true));
 
@@ -354,19 +695,9 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
                 .set_bit()
         });
 
-        // Set gpio speed
-        let high_speed_pins = (
-            pins.0.set_speed(Speed::VeryHigh),
-            pins.1.set_speed(Speed::VeryHigh),
-            pins.2.set_speed(Speed::VeryHigh),
-            pins.3.set_speed(Speed::VeryHigh),
-            pins.4.set_speed(Speed::VeryHigh),
-            pins.5.set_speed(Speed::VeryHigh),
-        );
-
         let mut unit = Qspi {
             qspi,
-            _pins: high_speed_pins,
+            _pins: pins,
             config,
         };
         unit.apply_config(config);
@@ -388,7 +719,11 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
         self.config
     }
 
-    pub fn apply_config(&mut self, config: QspiConfig) {
+    /// Applies `config`, the flash bank and dual-flash mode stay the ones of the constructor
+    pub fn apply_config(&mut self, mut config: QspiConfig) {
+        config.flash_bank = self.config.flash_bank;
+        config.dual_flash = self.config.dual_flash;
+
         if self.qspi.sr.read().busy().bit_is_set() {
             self.abort_transmission();
         }
@@ -399,12 +734,16 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
 
         while self.qspi.sr.read().busy().bit_is_set() {}
 
-        // Modify the prescaler and select flash bank 2 - flash bank 1 is currently unsupported.
+        // Modify the prescaler and select the flash bank
         self.qspi.cr.modify(|_, w| unsafe {
             w.prescaler()
                 .bits(config.clock_prescaler as u8)
                 .sshift()
                 .bit(config.sample_shift == SampleShift::HalfACycle)
+                .fsel()
+                .bit(config.flash_bank == FlashBank::Bank2)
+                .dfm()
+                .bit(config.dual_flash)
         });
         while self.is_busy() {}
 
@@ -431,97 +770,10 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
             return Err(QspiError::Busy);
         }
 
-        // If double data rate change shift
-        if command.double_data_rate {
-            self.qspi.cr.modify(|_, w| w.sshift().bit(false));
-        }
-        while self.is_busy() {}
-
         // Clear the transfer complete flag.
         self.qspi.fcr.modify(|_, w| w.ctcf().set_bit());
 
-        let mut dmode: u8 = 0;
-        let mut instruction: u8 = 0;
-        let mut imode: u8 = 0;
-        let mut admode: u8 = 0;
-        let mut adsize: u8 = 0;
-        let mut abmode: u8 = 0;
-        let mut absize: u8 = 0;
-
-        // Write the length and format of data
-        if command.receive_length > 0 {
-            self.qspi
-                .dlr
-                .write(|w| unsafe { w.dl().bits(command.receive_length as u32 - 1) });
-            if self.config.qpi_mode {
-                dmode = QspiMode::QuadChannel as u8;
-            } else {
-                dmode = command.data_mode as u8;
-            }
-        }
-
-        // Write instruction mode
-        if let Some((inst, mode)) = command.instruction {
-            if self.config.qpi_mode {
-                imode = QspiMode::QuadChannel as u8;
-            } else {
-                imode = mode as u8;
-            }
-            instruction = inst;
-        }
-
-        // Note Address mode
-        if let Some((_, mode)) = command.address {
-            if self.config.qpi_mode {
-                admode = QspiMode::QuadChannel as u8;
-            } else {
-                admode = mode as u8;
-            }
-            adsize = self.config.address_size as u8;
-        }
-
-        // Write Alternative bytes
-        if let Some((a_bytes, mode)) = command.alternative_bytes {
-            if self.config.qpi_mode {
-                abmode = QspiMode::QuadChannel as u8;
-            } else {
-                abmode = mode as u8;
-            }
-
-            absize = a_bytes.len() as u8 - 1;
-
-            self.qspi.abr.write(|w| {
-                let mut reg_byte: u32 = 0;
-                for (i, element) in a_bytes.iter().rev().enumerate() {
-                    reg_byte |= (*element as u32) << (i * 8);
-                }
-                unsafe { w.alternate().bits(reg_byte) }
-            });
-        }
-
-        // Write CCR register with instruction etc.
-        self.qspi.ccr.modify(|_, w| unsafe {
-            w.fmode()
-                .bits(0b01)
-                .admode()
-                .bits(admode)
-                .adsize()
-                .bits(adsize)
-                .abmode()
-                .bits(abmode)
-                .absize()
-                .bits(absize)
-                .ddrm()
-                .bit(command.double_data_rate)
-                .dcyc()
-                .bits(command.dummy_cycles)
-                .dmode()
-                .bits(dmode)
-                .imode()
-                .bits(imode)
-                .instruction()
-                .bits(instruction)
-        });
+        self.setup_command(0b01, &Phases::from(&command));
 
         // Write address, triggers send
         if let Some((addr, _)) = command.address {
@@ -535,7 +787,7 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
 
         // Transfer error
         if self.qspi.sr.read().tef().bit_is_set() {
-            return Err(QspiError::Unknown);
+            return Err(QspiError::TransferError);
         }
 
         // Read data from the buffer
@@ -566,10 +818,7 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
             if self.is_busy() {
                 self.abort_transmission();
             }
-            self.qspi.cr.modify(|_, w| {
-                w.sshift()
-                    .bit(self.config.sample_shift == SampleShift::HalfACycle)
-            });
+            self.restore_sample_shift();
         }
         while self.is_busy() {}
         self.qspi.fcr.write(|w| w.ctcf().set_bit());
@@ -583,6 +832,250 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
         // Clear the transfer complete flag.
         self.qspi.fcr.modify(|_, w| w.ctcf().set_bit());
 
+        self.setup_command(0b00, &Phases::from(&command));
+
+        // Write address, triggers send
+        if let Some((addr, _)) = command.address {
+            self.qspi.ar.write(|w| unsafe { w.address().bits(addr) });
+        }
+
+        // Transfer error
+        if self.qspi.sr.read().tef().bit_is_set() {
+            return Err(QspiError::TransferError);
+        }
+
+        // Write data to the FIFO
+        if let Some((data, _)) = command.data {
+            for byte in data {
+                while self.qspi.sr.read().ftf().bit_is_clear() {}
+                unsafe {
+                    ptr::write_volatile(&self.qspi.dr as *const _ as *mut u8, *byte);
+                }
+            }
+        }
+
+        while self.qspi.sr.read().tcf().bit_is_clear() {}
+
+        self.qspi.fcr.write(|w| w.ctcf().set_bit());
+
+        if self.is_busy() {}
+
+        if command.double_data_rate {
+            self.restore_sample_shift();
+        }
+        Ok(())
+    }
+
+    /// Starts polling a status register of the flash with `command` until the status matches
+    /// `polling`, the CPU is free in the meantime
+    ///
+    /// The status is 1 to 4 bytes long (`command.receive_length`), other lengths return
+    /// [`QspiError::Length`]. The end of the polling is checked with
+    /// [`Qspi::check_status_match`], for example from the interrupt enabled by
+    /// [`Qspi::listen_status_match`].
+    pub fn start_status_polling(
+        &self,
+        command: QspiReadCommand,
+        polling: QspiStatusPolling,
+    ) -> Result<(), QspiError> {
+        if !(1..=4).contains(&command.receive_length) {
+            return Err(QspiError::Length);
+        }
+
+        if self.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        self.qspi.fcr.write(|w| w.csmf().set_bit().ctef().set_bit());
+
+        self.qspi
+            .psmkr
+            .write(|w| unsafe { w.mask().bits(polling.mask) });
+        self.qspi
+            .psmar
+            .write(|w| unsafe { w.match_().bits(polling.match_value) });
+        self.qspi
+            .pir
+            .write(|w| unsafe { w.interval().bits(polling.interval) });
+
+        // Stop polling on the first match
+        self.qspi.cr.modify(|_, w| {
+            w.pmm()
+                .bit(polling.match_mode == PollingMatchMode::Or)
+                .apms()
+                .set_bit()
+        });
+
+        self.setup_command(0b10, &Phases::from(&command));
+
+        // Write address, triggers send
+        if let Some((addr, _)) = command.address {
+            self.qspi.ar.write(|w| unsafe { w.address().bits(addr) });
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the status polling started by [`Qspi::start_status_polling`] matched, and
+    /// returns the matching status
+    pub fn check_status_match(&self) -> nb::Result<u32, QspiError> {
+        let sr = self.qspi.sr.read();
+
+        if sr.tef().bit_is_set() {
+            self.qspi.fcr.write(|w| w.ctef().set_bit());
+            return Err(nb::Error::Other(QspiError::TransferError));
+        }
+
+        if sr.smf().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // The last status read is in the data register
+        let status = unsafe { ptr::read_volatile(&self.qspi.dr as *const _ as *const u32) };
+        self.qspi.fcr.write(|w| w.csmf().set_bit());
+        while self.is_busy() {}
+
+        Ok(status)
+    }
+
+    /// Polls a status register of the flash with `command` until it matches `polling`
+    ///
+    /// Gives up with [`QspiError::Timeout`] if the status did not match after `budget` checks
+    /// of the status flags, `None` waits forever.
+    pub fn poll_status(
+        &self,
+        command: QspiReadCommand,
+        polling: QspiStatusPolling,
+        budget: Option<u32>,
+    ) -> Result<u32, QspiError> {
+        self.start_status_polling(command, polling)?;
+
+        let mut remaining = budget;
+        loop {
+            match self.check_status_match() {
+                Ok(status) => return Ok(status),
+                Err(nb::Error::Other(error)) => return Err(error),
+                Err(nb::Error::WouldBlock) => {}
+            }
+
+            if let Some(remaining) = remaining.as_mut() {
+                if *remaining == 0 {
+                    self.abort_transmission();
+                    return Err(QspiError::Timeout);
+                }
+
+                *remaining -= 1;
+            }
+        }
+    }
+
+    /// Enables the status match interrupt
+    pub fn listen_status_match(&mut self) {
+        self.qspi.cr.modify(|_, w| w.smie().set_bit());
+    }
+
+    /// Disables the status match interrupt
+    pub fn unlisten_status_match(&mut self) {
+        self.qspi.cr.modify(|_, w| w.smie().clear_bit());
+    }
+
+    /// Switches to memory-mapped mode, `command` is then issued by the peripheral to read the
+    /// flash on each access to the memory region starting at [`MEMORY_MAPPED_BASE`]
+    ///
+    /// Any ongoing transaction is aborted.
+    pub fn memory_mapped(self, command: QspiMemoryMappedCommand) -> QspiMemoryMapped<PINS> {
+        if self.is_busy() {
+            self.abort_transmission();
+        }
+
+        let mode = |mode: QspiMode| {
+            if self.config.qpi_mode && mode != QspiMode::NoPhase {
+                QspiMode::QuadChannel as u8
+            } else {
+                mode as u8
+            }
+        };
+
+        let mut instruction: u8 = 0;
+        let mut imode: u8 = 0;
+        let mut abmode: u8 = 0;
+        let mut absize: u8 = 0;
+
+        if let Some((inst, inst_mode)) = command.instruction {
+            imode = mode(inst_mode);
+            instruction = inst;
+        }
+
+        if let Some((a_bytes, a_mode)) = command.alternative_bytes {
+            abmode = mode(a_mode);
+            absize = a_bytes.len() as u8 - 1;
+
+            self.qspi.abr.write(|w| {
+                let mut reg_byte: u32 = 0;
+                for (i, element) in a_bytes.iter().rev().enumerate() {
+                    reg_byte |= (*element as u32) << (i * 8);
+                }
+                unsafe { w.alternate().bits(reg_byte) }
+            });
+        }
+
+        // The timeout counter releases nCS once the bus has been idle for `timeout` cycles
+        if let Some(timeout) = command.timeout {
+            self.qspi
+                .lptr
+                .write(|w| unsafe { w.timeout().bits(timeout) });
+        }
+        self.qspi
+            .cr
+            .modify(|_, w| w.tcen().bit(command.timeout.is_some()));
+
+        if command.double_data_rate {
+            self.qspi.cr.modify(|_, w| w.sshift().bit(false));
+        }
+
+        // Write CCR register, the command is sent on the first access
+        self.qspi.ccr.modify(|_, w| unsafe {
+            w.fmode()
+                .bits(0b11)
+                .admode()
+                .bits(mode(command.address_mode))
+                .adsize()
+                .bits(self.config.address_size as u8)
+                .abmode()
+                .bits(abmode)
+                .absize()
+                .bits(absize)
+                .ddrm()
+                .bit(command.double_data_rate)
+                .dhhc()
+                .bit(command.double_data_rate && self.config.ddr_hold)
+                .dcyc()
+                .bits(command.dummy_cycles)
+                .dmode()
+                .bits(mode(command.data_mode))
+                .imode()
+                .bits(imode)
+                .instruction()
+                .bits(instruction)
+        });
+
+        QspiMemoryMapped { qspi: self }
+    }
+}
+
+impl<PINS> Qspi<PINS> {
+    /// Writes the data length, alternate bytes and communication configuration of a command
+    ///
+    /// The command starts when the address is written, or right away if it has no address.
+    fn setup_command(&self, fmode: u8, phases: &Phases) {
+        let mode = |mode: QspiMode| {
+            if self.config.qpi_mode && mode != QspiMode::NoPhase {
+                QspiMode::QuadChannel as u8
+            } else {
+                mode as u8
+            }
+        };
+
         let mut dmode: u8 = 0;
         let mut instruction: u8 = 0;
         let mut imode: u8 = 0;
@@ -591,46 +1084,32 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
         let mut abmode: u8 = 0;
         let mut absize: u8 = 0;
 
+        // If double data rate change shift
+        if phases.double_data_rate {
+            self.qspi.cr.modify(|_, w| w.sshift().bit(false));
+        }
+
         // Write the length and format of data
-        if let Some((data, mode)) = command.data {
-            self.qspi
-                .dlr
-                .write(|w| unsafe { w.dl().bits(data.len() as u32 - 1) });
-            if self.config.qpi_mode {
-                dmode = QspiMode::QuadChannel as u8;
-            } else {
-                dmode = mode as u8;
-            }
+        if let Some((length, data_mode)) = phases.data {
+            self.qspi.dlr.write(|w| unsafe { w.dl().bits(length - 1) });
+            dmode = mode(data_mode);
         }
 
         // Write instruction mode
-        if let Some((inst, mode)) = command.instruction {
-            if self.config.qpi_mode {
-                imode = QspiMode::QuadChannel as u8;
-            } else {
-                imode = mode as u8;
-            }
+        if let Some((inst, inst_mode)) = phases.instruction {
+            imode = mode(inst_mode);
             instruction = inst;
         }
 
         // Note Address mode
-        if let Some((_, mode)) = command.address {
-            if self.config.qpi_mode {
-                admode = QspiMode::QuadChannel as u8;
-            } else {
-                admode = mode as u8;
-            }
+        if let Some((_, address_mode)) = phases.address {
+            admode = mode(address_mode);
             adsize = self.config.address_size as u8;
         }
 
         // Write Alternative bytes
-        if let Some((a_bytes, mode)) = command.alternative_bytes {
-            if self.config.qpi_mode {
-                abmode = QspiMode::QuadChannel as u8;
-            } else {
-                abmode = mode as u8;
-            }
-
+        if let Some((a_bytes, a_mode)) = phases.alternative_bytes {
+            abmode = mode(a_mode);
             absize = a_bytes.len() as u8 - 1;
 
             self.qspi.abr.write(|w| {
@@ -642,14 +1121,10 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
             });
         }
 
-        if command.double_data_rate {
-            self.qspi.cr.modify(|_, w| w.sshift().bit(false));
-        }
-
         // Write CCR register with instruction etc.
         self.qspi.ccr.modify(|_, w| unsafe {
             w.fmode()
-                .bits(0b00)
+                .bits(fmode)
                 .admode()
                 .bits(admode)
                 .adsize()
@@ -659,9 +1134,11 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
                 .absize()
                 .bits(absize)
                 .ddrm()
-                .bit(command.double_data_rate)
+                .bit(phases.double_data_rate)
+                .dhhc()
+                .bit(phases.double_data_rate && self.config.ddr_hold)
                 .dcyc()
-                .bits(command.dummy_cycles)
+                .bits(phases.dummy_cycles)
                 .dmode()
                 .bits(dmode)
                 .imode()
@@ -669,40 +1146,279 @@ impl<CLK, NCS, IO0, IO1, IO2, IO3> Qspi<(CLK, NCS, IO0, IO1, IO2, IO3)> {
                 .instruction()
                 .bits(instruction)
         });
+    }
+
+    /// Restores the sample shift of the configuration after a double data rate command
+    fn restore_sample_shift(&self) {
+        self.qspi.cr.modify(|_, w| {
+            w.sshift()
+                .bit(self.config.sample_shift == SampleShift::HalfACycle)
+        });
+    }
+}
+
+/// QSPI with the data of indirect commands moved by DMA
+pub struct QspiDma<PINS, CHANNEL> {
+    qspi: Qspi<PINS>,
+    pub channel: CHANNEL,
+}
+
+impl<PINS, CHANNEL> QspiDma<PINS, CHANNEL>
+where
+    Self: TransferPayload,
+{
+    /// Starts `phases` with the DMA channel enabled
+    fn begin(&mut self, fmode: u8, phases: &Phases) {
+        let qspi = &self.qspi.qspi;
+        while qspi.sr.read().busy().bit_is_set() {}
+        qspi.fcr.write(|w| w.ctcf().set_bit().ctef().set_bit());
+
+        // NOTE(compiler_fence) operations on the buffer should not be reordered after the next
+        // statement, which starts the DMA transfer
+        atomic::compiler_fence(Ordering::Release);
+
+        self.start();
+        self.qspi.setup_command(fmode, phases);
 
         // Write address, triggers send
-        if let Some((addr, _)) = command.address {
-            self.qspi.ar.write(|w| unsafe { w.address().bits(addr) });
+        if let Some((addr, _)) = phases.address {
+            self.qspi
+                .qspi
+                .ar
+                .write(|w| unsafe { w.address().bits(addr) });
         }
+    }
 
-        // Transfer error
-        if self.qspi.sr.read().tef().bit_is_set() {
-            return Err(QspiError::Unknown);
+    fn poll(&mut self) -> nb::Result<(), QspiError> {
+        let qspi = &self.qspi.qspi;
+        let sr = qspi.sr.read();
+
+        if sr.tef().bit_is_set() {
+            qspi.fcr.write(|w| w.ctef().set_bit());
+            return Err(nb::Error::Other(QspiError::TransferError));
         }
 
-        // Write data to the FIFO
-        if let Some((data, _)) = command.data {
-            for byte in data {
-                while self.qspi.sr.read().ftf().bit_is_clear() {}
-                unsafe {
-                    ptr::write_volatile(&self.qspi.dr as *const _ as *mut u8, *byte);
-                }
+        // In indirect read mode TCF is set once the data has been received in the FIFO, the DMA
+        // may still have to move the last bytes out of it
+        if sr.tcf().bit_is_clear() || sr.flevel().bits() > 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        qspi.fcr.write(|w| w.ctcf().set_bit());
+        Ok(())
+    }
+}
+
+impl<MODE, BUFFER, PINS, CHANNEL> dma::Transfer<MODE, BUFFER, QspiDma<PINS, CHANNEL>>
+where
+    QspiDma<PINS, CHANNEL>: TransferPayload,
+{
+    /// Checks if the command has completed and the DMA has emptied the FIFO
+    pub fn poll(&mut self) -> nb::Result<(), QspiError> {
+        self.payload_mut().poll()
+    }
+
+    /// Blocks until the command has completed, returns the buffer, the QSPI and the result of
+    /// the command
+    pub fn wait(mut self) -> (BUFFER, QspiDma<PINS, CHANNEL>, Result<(), QspiError>) {
+        let result = nb::block!(self.poll());
+
+        let payload = self.payload_mut();
+        if result.is_err() {
+            payload.qspi.qspi.cr.modify(|_, w| w.abort().set_bit());
+        }
+        while payload.qspi.qspi.sr.read().busy().bit_is_set() {}
+        payload.stop();
+        payload.qspi.restore_sample_shift();
+
+        // NOTE(compiler_fence) operations on the buffer should not be reordered before the
+        // previous statement, which marks the DMA transfer as done
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let (buffer, payload) = self.extract_inner_without_drop();
+
+        (buffer, payload, result)
+    }
+}
+
+/// DMA channel with the QUADSPI request
+pub trait DmaChannel: private::Sealed {
+    #[doc(hidden)]
+    fn setup(&mut self);
+}
+
+impl<PINS> Qspi<PINS> {
+    /// Moves the data of indirect commands with `channel`
+    pub fn with_dma<CHANNEL: DmaChannel>(self, mut channel: CHANNEL) -> QspiDma<PINS, CHANNEL> {
+        // Perform one-time setup actions to keep the work minimal when using the driver.
+        channel.setup();
+
+        QspiDma {
+            qspi: self,
+            channel,
+        }
+    }
+}
+
+macro_rules! qspi_dma {
+    ($CH:path, $CHX:ident, $MAPX:ident) => {
+        impl private::Sealed for $CH {}
+
+        impl DmaChannel for $CH {
+            fn setup(&mut self) {
+                let channel = self;
+                channel.set_peripheral_address(
+                    unsafe { &(*QUADSPI::ptr()).dr as *const _ as u32 },
+                    false,
+                );
+                channel.cselr().modify(|_, w| w.$CHX().$MAPX());
+                channel.ccr().modify(|_, w| {
+                    w
+                        // memory to memory mode disabled
+                        .mem2mem()
+                        .clear_bit()
+                        // medium channel priority level
+                        .pl()
+                        .medium()
+                        // 8-bit memory size
+                        .msize()
+                        .bits8()
+                        // 8-bit peripheral size
+                        .psize()
+                        .bits8()
+                        // circular mode disabled
+                        .circ()
+                        .clear_bit()
+                });
             }
         }
 
-        while self.qspi.sr.read().tcf().bit_is_clear() {}
+        impl<PINS> QspiDma<PINS, $CH> {
+            /// Reads the data of `command` into `buffer`, the receive length is the length of
+            /// `buffer`
+            pub fn read<B>(
+                mut self,
+                command: QspiReadCommand,
+                mut buffer: B,
+            ) -> dma::Transfer<dma::W, B, Self>
+            where
+                B: StaticWriteBuffer<Word = u8>,
+            {
+                // NOTE(unsafe) We own the buffer now and we won't call other `&mut` on it
+                // until the end of the transfer.
+                let (ptr, len) = unsafe { buffer.static_write_buffer() };
+                assert!(len > 0 && len <= u16::MAX as usize);
+
+                // write to memory
+                self.channel.ccr().modify(|_, w| w.dir().clear_bit());
+                self.channel.set_memory_address(ptr as u32, true);
+                self.channel.set_transfer_length(len as u16);
+
+                self.begin(0b01, &Phases::from(&command.receive_length(len as u32)));
+
+                dma::Transfer::w(buffer, self)
+            }
 
-        self.qspi.fcr.write(|w| w.ctcf().set_bit());
+            /// Writes `buffer` as the data of `command`
+            pub fn write<B>(
+                mut self,
+                command: QspiWriteCommand,
+                buffer: B,
+            ) -> dma::Transfer<dma::R, B, Self>
+            where
+                B: StaticReadBuffer<Word = u8>,
+            {
+                // NOTE(unsafe) We own the buffer now and we won't call other `&mut` on it
+                // until the end of the transfer.
+                let (ptr, len) = unsafe { buffer.static_read_buffer() };
+                assert!(len > 0 && len <= u16::MAX as usize);
+
+                // write to peripheral
+                self.channel.ccr().modify(|_, w| w.dir().set_bit());
+                self.channel.set_memory_address(ptr as u32, true);
+                self.channel.set_transfer_length(len as u16);
+
+                let mut phases = Phases::from(&command);
+                phases.data = Some((
+                    len as u32,
+                    command
+                        .data
+                        .map_or(QspiMode::SingleChannel, |(_, mode)| mode),
+                ));
+                self.begin(0b00, &phases);
+
+                dma::Transfer::r(buffer, self)
+            }
 
-        if self.is_busy() {}
+            /// Stops the DMA and returns the QSPI and the DMA channel
+            pub fn split(mut self) -> (Qspi<PINS>, $CH) {
+                self.stop();
+                (self.qspi, self.channel)
+            }
+        }
 
-        if command.double_data_rate {
-            self.qspi.cr.modify(|_, w| {
-                w.sshift()
-                    .bit(self.config.sample_shift == SampleShift::HalfACycle)
-            });
+        impl<PINS> dma::TransferPayload for QspiDma<PINS, $CH> {
+            fn start(&mut self) {
+                self.channel.start();
+                self.qspi.qspi.cr.modify(|_, w| w.dmaen().set_bit());
+            }
+
+            fn stop(&mut self) {
+                self.channel.stop();
+                self.qspi.qspi.cr.modify(|_, w| w.dmaen().clear_bit());
+            }
         }
-        Ok(())
+    };
+}
+
+qspi_dma!(dma1::C5, c5s, map5);
+qspi_dma!(dma2::C7, c7s, map3);
+
+/// QSPI in memory-mapped mode, created by [`Qspi::memory_mapped`]
+///
+/// The flash is read like internal memory (including code execution), indirect commands are not
+/// available until [`QspiMemoryMapped::exit`].
+pub struct QspiMemoryMapped<PINS> {
+    qspi: Qspi<PINS>,
+}
+
+impl<PINS> QspiMemoryMapped<PINS> {
+    /// Returns the memory-mapped flash, its length is the flash size of the configuration
+    pub fn as_slice(&self) -> &[u8] {
+        // NOTE(unsafe) The region is read-only and stays mapped while `self` is borrowed
+        unsafe { slice::from_raw_parts(MEMORY_MAPPED_BASE as *const u8, self.len()) }
+    }
+
+    /// Returns the memory-mapped flash for the rest of the program, the QSPI stays in
+    /// memory-mapped mode
+    pub fn into_static(self) -> &'static [u8] {
+        // NOTE(unsafe) `self` is consumed, so the memory-mapped mode cannot be exited
+        unsafe { slice::from_raw_parts(MEMORY_MAPPED_BASE as *const u8, self.len()) }
+    }
+
+    /// Length of the memory-mapped flash, 2^(FSIZE + 1) bytes up to the 256 MB of the region
+    fn len(&self) -> usize {
+        1 << (self.qspi.config.flash_size + 1).min(28)
+    }
+
+    /// Leaves memory-mapped mode and returns to indirect mode
+    pub fn exit(self) -> Qspi<PINS> {
+        let qspi = &self.qspi.qspi;
+
+        // Aborting is the only way to leave memory-mapped mode
+        qspi.cr.modify(|_, w| w.abort().set_bit());
+        while qspi.sr.read().busy().bit_is_set() {}
+
+        qspi.ccr.modify(|_, w| unsafe { w.fmode().bits(0b00) });
+        qspi.cr.modify(|_, w| {
+            w.tcen()
+                .clear_bit()
+                .sshift()
+                .bit(self.qspi.config.sample_shift == SampleShift::HalfACycle)
+        });
+        qspi.fcr.write(|w| w.ctof().set_bit());
+
+        self.qspi
     }
 }
 
@@ -767,3 +1483,18 @@ pins!(
     IO2: [PC4, PF7],
     IO3: [PC5, PF6]
 );
+
+// Bank 2 of STM32L471xx, STM32L475xx, STM32L476xx, STM32L486xx, STM32L496xx and STM32L4A6xx
+#[cfg(any(feature = "stm32l4x1", feature = "stm32l4x5", feature = "stm32l4x6"))]
+pins!(
+    QUADSPI,
+    AF10,
+    BK2_nCS: [PD3],
+    BK2_IO0: [PD4],
+    BK2_IO1: [PD5],
+    BK2_IO2: [PD6],
+    BK2_IO3: [PD7]
+);
+
+#[cfg(feature = "stm32l4x5")]
+pins!(AF10, Sealed: [PD3, PD4, PD5, PD6, PD7]);
@@ -0,0 +1,978 @@
+//! Generic SPI-NOR flash driver
+//!
+//! The size, the page and erase sizes, the quad read command, the quad enable method and the
+//! addressing mode of the flash are discovered from its SFDP tables (JESD216). The flash is
+//! accessed through a [`Transport`], which is implemented by [`Qspi`].
+
+use super::{
+    AddressSize, Qspi, QspiError, QspiMode, QspiReadCommand, QspiStatusPolling, QspiWriteCommand,
+};
+
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const WRITE_STATUS: u8 = 0x01;
+const READ_STATUS_2: u8 = 0x35;
+const WRITE_STATUS_2: u8 = 0x31;
+const READ_STATUS_2_BIT_7: u8 = 0x3F;
+const WRITE_STATUS_2_BIT_7: u8 = 0x3E;
+const READ_ID: u8 = 0x9F;
+const READ_SFDP: u8 = 0x5A;
+const FAST_READ: u8 = 0x0B;
+const PAGE_PROGRAM: u8 = 0x02;
+const CHIP_ERASE: u8 = 0xC7;
+const ENTER_FOUR_BYTE_ADDRESS: u8 = 0xB7;
+const DEEP_POWER_DOWN: u8 = 0xB9;
+const RELEASE_POWER_DOWN: u8 = 0xAB;
+
+/// Write in progress bit of the status register
+const STATUS_BUSY: u8 = 0x01;
+
+/// `SFDP` in little endian
+const SFDP_SIGNATURE: u32 = 0x5044_4653;
+
+/// Number of DWORDs of the basic flash parameter table used by the driver (JESD216B)
+const BFPT_DWORDS: usize = 16;
+
+/// Command sent to the flash, the instruction is always sent on a single line
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Command {
+    pub instruction: u8,
+    /// Address, its size and the lines it is sent on
+    pub address: Option<(u32, AddressSize, QspiMode)>,
+    /// Mode byte sent after the address, on the lines of the address
+    pub mode_byte: Option<u8>,
+    pub dummy_cycles: u8,
+    /// Lines the data is sent or received on
+    pub data_mode: QspiMode,
+}
+
+impl Command {
+    pub fn new(instruction: u8) -> Self {
+        Command {
+            instruction,
+            address: None,
+            mode_byte: None,
+            dummy_cycles: 0,
+            data_mode: QspiMode::SingleChannel,
+        }
+    }
+
+    pub fn address(self, address: u32, size: AddressSize, mode: QspiMode) -> Self {
+        Command {
+            address: Some((address, size, mode)),
+            ..self
+        }
+    }
+
+    pub fn mode_byte(self, byte: u8) -> Self {
+        Command {
+            mode_byte: Some(byte),
+            ..self
+        }
+    }
+
+    pub fn dummy_cycles(self, n: u8) -> Self {
+        Command {
+            dummy_cycles: n,
+            ..self
+        }
+    }
+
+    pub fn data_mode(self, mode: QspiMode) -> Self {
+        Command {
+            data_mode: mode,
+            ..self
+        }
+    }
+}
+
+/// Bus the flash is connected to
+pub trait Transport {
+    type Error;
+
+    /// Sends `command` and reads `buffer.len()` bytes, no data phase if `buffer` is empty
+    fn read(&mut self, command: &Command, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Sends `command` followed by `data`, no data phase if `data` is empty
+    fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Reads the one byte status of `command` until its bits set in `mask` are equal to the
+    /// ones of `value`
+    fn poll_status(&mut self, command: &Command, mask: u8, value: u8) -> Result<(), Self::Error> {
+        let mut status = [0];
+        loop {
+            self.read(command, &mut status)?;
+            if status[0] & mask == value & mask {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error<E> {
+    /// Error of the transport
+    Transport(E),
+    /// No flash answered the JEDEC ID command
+    NoDevice,
+    /// The SFDP tables are missing or not supported
+    Sfdp,
+    /// The access is out of the flash
+    OutOfBounds,
+    /// The erase address is not aligned on the erase size
+    NotAligned,
+}
+
+/// Erase command of the flash
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EraseType {
+    /// Size erased in bytes
+    pub size: u32,
+    pub instruction: u8,
+}
+
+/// How the quad enable (QE) bit is set, SFDP quad enable requirements
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QuadEnable {
+    /// The flash has no QE bit
+    None,
+    /// QE is bit 6 of status register 1
+    Sr1Bit6,
+    /// QE is bit 1 of status register 2, written with status register 1 by a two bytes write
+    /// status, status register 2 is read with 0x35 if `readable`
+    Sr2Bit1 { readable: bool },
+    /// QE is bit 1 of status register 2, read with 0x35 and written with 0x31
+    Sr2Bit1Direct,
+    /// QE is bit 7 of status register 2, read with 0x3F and written with 0x3E
+    Sr2Bit7,
+}
+
+/// Addressing mode of the flash
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Addressing {
+    ThreeBytes,
+    FourBytes,
+    /// Three bytes addresses until the flash is switched to four bytes addresses with 0xB7, after
+    /// a write enable if `write_enable`
+    EnterFourBytes {
+        write_enable: bool,
+    },
+}
+
+/// Read command of the flash
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReadCommand {
+    pub instruction: u8,
+    pub address_mode: QspiMode,
+    pub data_mode: QspiMode,
+    /// Clocks of the mode bits, sent after the address on the same lines
+    pub mode_clocks: u8,
+    pub dummy_cycles: u8,
+}
+
+/// Parameters of the flash, from its basic flash parameter table
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parameters {
+    /// Size of the flash in bytes
+    pub size: u32,
+    pub page_size: u32,
+    /// Supported erase types, smallest first
+    pub erase_types: [Option<EraseType>; 4],
+    pub addressing: Addressing,
+    /// `None` if the table does not describe it
+    pub quad_enable: Option<QuadEnable>,
+    /// Fastest read command the driver can use
+    pub read: ReadCommand,
+}
+
+impl Parameters {
+    /// Decodes the basic flash parameter table, `table` holds its DWORDs in order
+    pub fn from_bfpt(table: &[u32]) -> Option<Self> {
+        // JESD216 tables have at least 9 DWORDs
+        if table.len() < 9 {
+            return None;
+        }
+        let dword = |n: usize| table.get(n - 1).copied();
+
+        let dw1 = table[0];
+        let dw2 = table[1];
+
+        let size = if dw2 & (1 << 31) == 0 {
+            // N + 1 bits
+            ((dw2 as u64 + 1) / 8) as u32
+        } else {
+            // 2^N bits
+            let n = dw2 & 0x7FFF_FFFF;
+            if !(3..=34).contains(&n) {
+                return None;
+            }
+            1 << (n - 3)
+        };
+
+        let addressing = match (dw1 >> 17) & 0b11 {
+            0b00 => Addressing::ThreeBytes,
+            0b01 if size > (1 << 24) => Addressing::EnterFourBytes {
+                write_enable: dword(16).is_some_and(|dw16| dw16 & (1 << 25) != 0),
+            },
+            0b01 => Addressing::ThreeBytes,
+            0b10 => Addressing::FourBytes,
+            _ => return None,
+        };
+
+        let mut erase_types = [None; 4];
+        for (i, erase_type) in erase_types.iter_mut().enumerate() {
+            let bits = (table[7 + i / 2] >> (16 * (i % 2))) & 0xFFFF;
+            let n = bits & 0xFF;
+            if n != 0 && n < 32 {
+                *erase_type = Some(EraseType {
+                    size: 1 << n,
+                    instruction: (bits >> 8) as u8,
+                });
+            }
+        }
+        // 4 kB erase of the first table revision
+        if erase_types.iter().all(Option::is_none) && dw1 & 0b11 == 0b01 {
+            erase_types[0] = Some(EraseType {
+                size: 4096,
+                instruction: (dw1 >> 8) as u8,
+            });
+        }
+        erase_types.sort_unstable_by_key(|erase_type| erase_type.map_or(u32::MAX, |e| e.size));
+
+        let page_size = dword(11).map_or(256, |dw11| 1 << ((dw11 >> 4) & 0xF));
+
+        let quad_enable = dword(15).and_then(|dw15| match (dw15 >> 20) & 0b111 {
+            0b000 => Some(QuadEnable::None),
+            0b001 => Some(QuadEnable::Sr2Bit1 { readable: false }),
+            0b010 => Some(QuadEnable::Sr1Bit6),
+            0b011 => Some(QuadEnable::Sr2Bit7),
+            0b100 | 0b101 => Some(QuadEnable::Sr2Bit1 { readable: true }),
+            0b110 => Some(QuadEnable::Sr2Bit1Direct),
+            _ => None,
+        });
+
+        let fast_read = |bits: u32, address_mode| ReadCommand {
+            instruction: (bits >> 8) as u8,
+            address_mode,
+            data_mode: QspiMode::QuadChannel,
+            mode_clocks: ((bits >> 5) & 0b111) as u8,
+            dummy_cycles: (bits & 0b1_1111) as u8,
+        };
+        let dw3 = table[2];
+        // Quad reads are only used if the QE bit can be set
+        let read = if quad_enable.is_some() && dw1 & (1 << 21) != 0 && dw3 & 0xFF00 != 0 {
+            fast_read(dw3 & 0xFFFF, QspiMode::QuadChannel)
+        } else if quad_enable.is_some() && dw1 & (1 << 22) != 0 && dw3 & 0xFF00_0000 != 0 {
+            fast_read(dw3 >> 16, QspiMode::SingleChannel)
+        } else {
+            ReadCommand {
+                instruction: FAST_READ,
+                address_mode: QspiMode::SingleChannel,
+                data_mode: QspiMode::SingleChannel,
+                mode_clocks: 0,
+                dummy_cycles: 8,
+            }
+        };
+
+        Some(Parameters {
+            size,
+            page_size,
+            erase_types,
+            addressing,
+            quad_enable,
+            read,
+        })
+    }
+
+    /// Size of the addresses once the flash is initialized
+    pub fn address_size(&self) -> AddressSize {
+        match self.addressing {
+            Addressing::ThreeBytes => AddressSize::Addr24Bit,
+            _ => AddressSize::Addr32Bit,
+        }
+    }
+}
+
+/// SPI-NOR flash
+pub struct Nor<T> {
+    transport: T,
+    id: [u8; 3],
+    parameters: Parameters,
+}
+
+impl<T: Transport> Nor<T> {
+    /// Discovers the flash on `transport` and initializes it: quad mode is enabled if the quad
+    /// read command is used, and 4 bytes addresses are enabled on flashes larger than 16 MB
+    pub fn new(mut transport: T) -> Result<Self, Error<T::Error>> {
+        let mut id = [0; 3];
+        transport
+            .read(&Command::new(READ_ID), &mut id)
+            .map_err(Error::Transport)?;
+        if id == [0x00; 3] || id == [0xFF; 3] {
+            return Err(Error::NoDevice);
+        }
+
+        let parameters = Self::read_parameters(&mut transport)?;
+
+        let mut nor = Nor {
+            transport,
+            id,
+            parameters,
+        };
+
+        if nor.parameters.read.data_mode == QspiMode::QuadChannel {
+            nor.enable_quad()?;
+        }
+
+        if let Addressing::EnterFourBytes { write_enable } = nor.parameters.addressing {
+            if write_enable {
+                nor.write_enable()?;
+            }
+            nor.command(ENTER_FOUR_BYTE_ADDRESS)?;
+        }
+
+        Ok(nor)
+    }
+
+    /// Manufacturer ID followed by the two bytes device ID
+    pub fn jedec_id(&self) -> [u8; 3] {
+        self.id
+    }
+
+    pub fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+
+    /// Reads `buffer.len()` bytes at `address` with the fastest read command
+    pub fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, buffer.len())?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let read = self.parameters.read;
+        let mut command = Command::new(read.instruction)
+            .address(address, self.parameters.address_size(), read.address_mode)
+            .dummy_cycles(read.dummy_cycles)
+            .data_mode(read.data_mode);
+        // The mode bits are sent as a byte that does not enable continuous read, otherwise the
+        // mode clocks are left as dummy cycles
+        if read.mode_clocks * lines(read.address_mode) == 8 {
+            command = command.mode_byte(0xFF);
+        } else {
+            command = command.dummy_cycles(read.dummy_cycles + read.mode_clocks);
+        }
+
+        self.transport
+            .read(&command, buffer)
+            .map_err(Error::Transport)
+    }
+
+    /// Programs `data` at `address`, the bytes must have been erased before
+    pub fn program(&mut self, mut address: u32, mut data: &[u8]) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, data.len())?;
+
+        while !data.is_empty() {
+            // A page program wraps around at the end of the page
+            let page_remaining = self.parameters.page_size - address % self.parameters.page_size;
+            let (chunk, rest) = data.split_at(data.len().min(page_remaining as usize));
+
+            self.write_enable()?;
+            let command = self.address_command(PAGE_PROGRAM, address);
+            self.transport
+                .write(&command, chunk)
+                .map_err(Error::Transport)?;
+            self.wait_ready()?;
+
+            address += chunk.len() as u32;
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Erases the sector (smallest erase size) at `address`
+    pub fn erase_sector(&mut self, address: u32) -> Result<(), Error<T::Error>> {
+        let erase_type = self.parameters.erase_types[0].ok_or(Error::Sfdp)?;
+        self.erase(address, erase_type)
+    }
+
+    /// Erases the block (largest erase size) at `address`
+    pub fn erase_block(&mut self, address: u32) -> Result<(), Error<T::Error>> {
+        let erase_type = self
+            .parameters
+            .erase_types
+            .iter()
+            .rev()
+            .find_map(|erase_type| *erase_type)
+            .ok_or(Error::Sfdp)?;
+        self.erase(address, erase_type)
+    }
+
+    /// Erases `erase_type.size` bytes at `address`, which must be aligned on the size
+    pub fn erase(&mut self, address: u32, erase_type: EraseType) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, erase_type.size as usize)?;
+        if !address.is_multiple_of(erase_type.size) {
+            return Err(Error::NotAligned);
+        }
+
+        self.write_enable()?;
+        let command = self.address_command(erase_type.instruction, address);
+        self.transport
+            .write(&command, &[])
+            .map_err(Error::Transport)?;
+        self.wait_ready()
+    }
+
+    /// Erases the whole flash
+    pub fn erase_chip(&mut self) -> Result<(), Error<T::Error>> {
+        self.write_enable()?;
+        self.command(CHIP_ERASE)?;
+        self.wait_ready()
+    }
+
+    /// Enters deep power-down, the flash ignores the commands other than
+    /// [`Nor::release_power_down`]
+    pub fn power_down(&mut self) -> Result<(), Error<T::Error>> {
+        self.command(DEEP_POWER_DOWN)
+    }
+
+    /// Leaves deep power-down, the flash needs tRES1 (a few µs) before the next command
+    pub fn release_power_down(&mut self) -> Result<(), Error<T::Error>> {
+        self.command(RELEASE_POWER_DOWN)
+    }
+
+    /// Releases the transport
+    pub fn free(self) -> T {
+        self.transport
+    }
+
+    fn read_parameters(transport: &mut T) -> Result<Parameters, Error<T::Error>> {
+        let mut header = [0; 16];
+        transport
+            .read(&sfdp_command(0), &mut header)
+            .map_err(Error::Transport)?;
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != SFDP_SIGNATURE {
+            return Err(Error::Sfdp);
+        }
+
+        // The first parameter header is the basic flash parameter table (ID 0xFF00)
+        let parameter = &header[8..];
+        if parameter[0] != 0x00 || parameter[7] != 0xFF {
+            return Err(Error::Sfdp);
+        }
+        let length = (parameter[3] as usize).min(BFPT_DWORDS);
+        let pointer = u32::from_le_bytes([parameter[4], parameter[5], parameter[6], 0]);
+
+        let mut bytes = [0; BFPT_DWORDS * 4];
+        transport
+            .read(&sfdp_command(pointer), &mut bytes[..length * 4])
+            .map_err(Error::Transport)?;
+
+        let mut table = [0; BFPT_DWORDS];
+        for (dword, bytes) in table.iter_mut().zip(bytes.chunks(4)) {
+            *dword = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        Parameters::from_bfpt(&table[..length]).ok_or(Error::Sfdp)
+    }
+
+    fn enable_quad(&mut self) -> Result<(), Error<T::Error>> {
+        match self.parameters.quad_enable {
+            None | Some(QuadEnable::None) => Ok(()),
+            Some(QuadEnable::Sr1Bit6) => {
+                let status = self.read_register(READ_STATUS)?;
+                if status & (1 << 6) == 0 {
+                    self.write_register(WRITE_STATUS, &[status | (1 << 6)])?;
+                }
+                Ok(())
+            }
+            Some(QuadEnable::Sr2Bit1 { readable }) => {
+                let status_2 = if readable {
+                    self.read_register(READ_STATUS_2)?
+                } else {
+                    0
+                };
+                if status_2 & (1 << 1) == 0 {
+                    let status = self.read_register(READ_STATUS)?;
+                    self.write_register(WRITE_STATUS, &[status, status_2 | (1 << 1)])?;
+                }
+                Ok(())
+            }
+            Some(QuadEnable::Sr2Bit1Direct) => {
+                let status_2 = self.read_register(READ_STATUS_2)?;
+                if status_2 & (1 << 1) == 0 {
+                    self.write_register(WRITE_STATUS_2, &[status_2 | (1 << 1)])?;
+                }
+                Ok(())
+            }
+            Some(QuadEnable::Sr2Bit7) => {
+                let status_2 = self.read_register(READ_STATUS_2_BIT_7)?;
+                if status_2 & (1 << 7) == 0 {
+                    self.write_register(WRITE_STATUS_2_BIT_7, &[status_2 | (1 << 7)])?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn read_register(&mut self, instruction: u8) -> Result<u8, Error<T::Error>> {
+        let mut value = [0];
+        self.transport
+            .read(&Command::new(instruction), &mut value)
+            .map_err(Error::Transport)?;
+        Ok(value[0])
+    }
+
+    fn write_register(&mut self, instruction: u8, value: &[u8]) -> Result<(), Error<T::Error>> {
+        self.write_enable()?;
+        self.transport
+            .write(&Command::new(instruction), value)
+            .map_err(Error::Transport)?;
+        self.wait_ready()
+    }
+
+    fn command(&mut self, instruction: u8) -> Result<(), Error<T::Error>> {
+        self.transport
+            .write(&Command::new(instruction), &[])
+            .map_err(Error::Transport)
+    }
+
+    fn write_enable(&mut self) -> Result<(), Error<T::Error>> {
+        self.command(WRITE_ENABLE)
+    }
+
+    fn wait_ready(&mut self) -> Result<(), Error<T::Error>> {
+        self.transport
+            .poll_status(&Command::new(READ_STATUS), STATUS_BUSY, 0)
+            .map_err(Error::Transport)
+    }
+
+    fn address_command(&self, instruction: u8, address: u32) -> Command {
+        Command::new(instruction).address(
+            address,
+            self.parameters.address_size(),
+            QspiMode::SingleChannel,
+        )
+    }
+
+    fn check_bounds(&self, address: u32, len: usize) -> Result<(), Error<T::Error>> {
+        if address as u64 + len as u64 > self.parameters.size as u64 {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+}
+
+/// Number of lines used by `mode`
+fn lines(mode: QspiMode) -> u8 {
+    match mode {
+        QspiMode::NoPhase => 0,
+        QspiMode::SingleChannel => 1,
+        QspiMode::DualChannel => 2,
+        QspiMode::QuadChannel => 4,
+    }
+}
+
+/// SFDP read, always 3 bytes addresses and 8 dummy cycles
+fn sfdp_command(address: u32) -> Command {
+    Command::new(READ_SFDP)
+        .address(address, AddressSize::Addr24Bit, QspiMode::SingleChannel)
+        .dummy_cycles(8)
+}
+
+/// The address size of the configuration is changed to the one of the commands
+impl<PINS> Transport for Qspi<PINS> {
+    type Error = QspiError;
+
+    fn read(&mut self, command: &Command, buffer: &mut [u8]) -> Result<(), QspiError> {
+        let mode_byte = [command.mode_byte.unwrap_or(0)];
+        let mut read = QspiReadCommand {
+            instruction: Some((command.instruction, QspiMode::SingleChannel)),
+            address: None,
+            alternative_bytes: None,
+            dummy_cycles: command.dummy_cycles,
+            data_mode: command.data_mode,
+            receive_length: buffer.len() as u32,
+            double_data_rate: false,
+        };
+        if let Some((address, size, mode)) = command.address {
+            self.config.address_size = size;
+            read = read.address(address, mode);
+            if command.mode_byte.is_some() {
+                read = read.alternative_bytes(&mode_byte, mode);
+            }
+        }
+
+        self.transfer(read, buffer)
+    }
+
+    fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), QspiError> {
+        let mut write = QspiWriteCommand {
+            instruction: Some((command.instruction, QspiMode::SingleChannel)),
+            address: None,
+            alternative_bytes: None,
+            dummy_cycles: command.dummy_cycles,
+            data: None,
+            double_data_rate: false,
+        };
+        if let Some((address, size, mode)) = command.address {
+            self.config.address_size = size;
+            write = write.address(address, mode);
+        }
+        if !data.is_empty() {
+            write = write.data(data, command.data_mode);
+        }
+
+        Qspi::write(self, write)
+    }
+
+    /// Polls the status with the automatic status polling of the peripheral
+    fn poll_status(&mut self, command: &Command, mask: u8, value: u8) -> Result<(), QspiError> {
+        let read = QspiReadCommand {
+            instruction: Some((command.instruction, QspiMode::SingleChannel)),
+            address: None,
+            alternative_bytes: None,
+            dummy_cycles: command.dummy_cycles,
+            data_mode: command.data_mode,
+            receive_length: 1,
+            double_data_rate: false,
+        };
+
+        Qspi::poll_status(
+            self,
+            read,
+            QspiStatusPolling::new(value as u32, mask as u32),
+            None,
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Addressing, Command, EraseType, Error, Nor, Parameters, QuadEnable, ReadCommand, Transport,
+    };
+    use crate::qspi::{AddressSize, QspiMode};
+
+    const SIZE: usize = 64 * 1024;
+
+    /// Basic flash parameter table of a 64 kB flash with 4 kB and 64 kB erases, 1-1-4 and 1-4-4
+    /// reads and QE in bit 1 of status register 2
+    const BFPT: [u32; 16] = [
+        0x0060_2001,
+        0x0007_FFFF,
+        0x6B08_EB44,
+        0,
+        0,
+        0,
+        0,
+        0xD810_200C,
+        0,
+        0,
+        0x0000_0080,
+        0,
+        0,
+        0,
+        0x0050_0000,
+        0,
+    ];
+
+    /// Flash answering the commands of the driver
+    struct Emulated {
+        memory: [u8; SIZE],
+        status: u8,
+        status_2: u8,
+        write_enabled: bool,
+        powered_down: bool,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Unexpected(u8);
+
+    impl Emulated {
+        fn new() -> Self {
+            Emulated {
+                memory: [0xFF; SIZE],
+                status: 0,
+                status_2: 0,
+                write_enabled: false,
+                powered_down: false,
+            }
+        }
+
+        fn sfdp(&self, address: usize) -> u8 {
+            let mut header = [0; 16];
+            header[..8].copy_from_slice(&[b'S', b'F', b'D', b'P', 0x06, 0x01, 0x00, 0xFF]);
+            header[8..].copy_from_slice(&[0x00, 0x06, 0x01, 16, 0x30, 0x00, 0x00, 0xFF]);
+
+            match address {
+                0..=15 => header[address],
+                0x30..=0x6F => BFPT[(address - 0x30) / 4].to_le_bytes()[address % 4],
+                _ => 0xFF,
+            }
+        }
+
+        fn address(command: &Command, mode: QspiMode) -> Result<usize, Unexpected> {
+            match command.address {
+                Some((address, AddressSize::Addr24Bit, m)) if m == mode => Ok(address as usize),
+                _ => Err(Unexpected(command.instruction)),
+            }
+        }
+
+        fn program(&mut self, command: &Command, data: &[u8]) -> Result<(), Unexpected> {
+            let address = Self::address(command, QspiMode::SingleChannel)?;
+            // Wraps around in the page
+            for (i, byte) in data.iter().enumerate() {
+                let page = address & !0xFF;
+                self.memory[page + (address + i) % 256] &= byte;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, command: &Command, size: usize) -> Result<(), Unexpected> {
+            let address = Self::address(command, QspiMode::SingleChannel)?;
+            assert_eq!(address % size, 0);
+            self.memory[address..address + size]
+                .iter_mut()
+                .for_each(|b| *b = 0xFF);
+            Ok(())
+        }
+    }
+
+    impl Transport for Emulated {
+        type Error = Unexpected;
+
+        fn read(&mut self, command: &Command, buffer: &mut [u8]) -> Result<(), Unexpected> {
+            if self.powered_down {
+                buffer.iter_mut().for_each(|b| *b = 0xFF);
+                return Ok(());
+            }
+
+            match command.instruction {
+                0x9F => buffer.copy_from_slice(&[0xEF, 0x40, 0x10]),
+                0x05 => buffer[0] = self.status,
+                0x35 => buffer[0] = self.status_2,
+                0x5A => {
+                    let address = Self::address(command, QspiMode::SingleChannel)?;
+                    assert_eq!(command.dummy_cycles, 8);
+                    for (i, byte) in buffer.iter_mut().enumerate() {
+                        *byte = self.sfdp(address + i);
+                    }
+                }
+                0xEB => {
+                    let address = Self::address(command, QspiMode::QuadChannel)?;
+                    let quad_enabled = self.status_2 & (1 << 1) != 0;
+                    if !quad_enabled
+                        || command.mode_byte != Some(0xFF)
+                        || command.dummy_cycles != 4
+                        || command.data_mode != QspiMode::QuadChannel
+                    {
+                        return Err(Unexpected(0xEB));
+                    }
+                    buffer.copy_from_slice(&self.memory[address..address + buffer.len()]);
+                }
+                instruction => return Err(Unexpected(instruction)),
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), Unexpected> {
+            if self.powered_down {
+                if command.instruction == 0xAB {
+                    self.powered_down = false;
+                }
+                return Ok(());
+            }
+
+            if command.instruction == 0x06 {
+                self.write_enabled = true;
+                return Ok(());
+            }
+            if command.instruction == 0xB9 {
+                self.powered_down = true;
+                return Ok(());
+            }
+            if command.instruction == 0xAB {
+                return Ok(());
+            }
+
+            // The other commands need a write enable
+            if !self.write_enabled {
+                return Err(Unexpected(command.instruction));
+            }
+            self.write_enabled = false;
+
+            match command.instruction {
+                0x01 => {
+                    self.status = data[0];
+                    self.status_2 = data[1];
+                }
+                0x02 => self.program(command, data)?,
+                0x20 => self.erase(command, 4096)?,
+                0xD8 => self.erase(command, 65536)?,
+                0xC7 => self.memory.iter_mut().for_each(|b| *b = 0xFF),
+                instruction => return Err(Unexpected(instruction)),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bfpt() {
+        let parameters = Parameters::from_bfpt(&BFPT).unwrap();
+
+        assert_eq!(parameters.size, 64 * 1024);
+        assert_eq!(parameters.page_size, 256);
+        assert_eq!(
+            parameters.erase_types,
+            [
+                Some(EraseType {
+                    size: 4096,
+                    instruction: 0x20
+                }),
+                Some(EraseType {
+                    size: 65536,
+                    instruction: 0xD8
+                }),
+                None,
+                None,
+            ]
+        );
+        assert_eq!(parameters.addressing, Addressing::ThreeBytes);
+        assert_eq!(
+            parameters.quad_enable,
+            Some(QuadEnable::Sr2Bit1 { readable: true })
+        );
+        assert_eq!(
+            parameters.read,
+            ReadCommand {
+                instruction: 0xEB,
+                address_mode: QspiMode::QuadChannel,
+                data_mode: QspiMode::QuadChannel,
+                mode_clocks: 2,
+                dummy_cycles: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn bfpt_quad_read() {
+        // Only 1-1-4 reads
+        let mut table = BFPT;
+        table[0] &= !(1 << 21);
+        let parameters = Parameters::from_bfpt(&table).unwrap();
+        assert_eq!(
+            parameters.read,
+            ReadCommand {
+                instruction: 0x6B,
+                address_mode: QspiMode::SingleChannel,
+                data_mode: QspiMode::QuadChannel,
+                mode_clocks: 0,
+                dummy_cycles: 8,
+            }
+        );
+
+        // Only 1-4-4 reads
+        let mut table = BFPT;
+        table[0] &= !(1 << 22);
+        let parameters = Parameters::from_bfpt(&table).unwrap();
+        assert_eq!(parameters.read.instruction, 0xEB);
+        assert_eq!(parameters.read.address_mode, QspiMode::QuadChannel);
+    }
+
+    #[test]
+    fn bfpt_revision_a() {
+        // 512 Mb flash with 3 or 4 bytes addresses and no quad enable requirements
+        let mut table = [0; 9];
+        table.copy_from_slice(&BFPT[..9]);
+        table[0] |= 0b01 << 17;
+        table[1] = (1 << 31) | 29;
+
+        let parameters = Parameters::from_bfpt(&table).unwrap();
+
+        assert_eq!(parameters.size, 64 * 1024 * 1024);
+        assert_eq!(parameters.page_size, 256);
+        assert_eq!(
+            parameters.addressing,
+            Addressing::EnterFourBytes {
+                write_enable: false
+            }
+        );
+        assert_eq!(parameters.address_size(), AddressSize::Addr32Bit);
+        // Quad reads are not used without a way to set the QE bit
+        assert_eq!(parameters.quad_enable, None);
+        assert_eq!(parameters.read.instruction, 0x0B);
+
+        assert_eq!(Parameters::from_bfpt(&BFPT[..8]), None);
+    }
+
+    #[test]
+    fn discovery() {
+        let nor = Nor::new(Emulated::new()).unwrap();
+
+        assert_eq!(nor.jedec_id(), [0xEF, 0x40, 0x10]);
+        assert_eq!(nor.parameters(), &Parameters::from_bfpt(&BFPT).unwrap());
+
+        // Quad mode is enabled for the 1-4-4 read
+        assert_eq!(nor.free().status_2, 1 << 1);
+    }
+
+    #[test]
+    fn program_read_erase() {
+        let mut nor = Nor::new(Emulated::new()).unwrap();
+
+        // Crosses a page and a sector boundary
+        let mut data = [0; 300];
+        data.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        nor.program(0x0FF0, &data).unwrap();
+
+        let mut read = [0; 300];
+        nor.read(0x0FF0, &mut read).unwrap();
+        assert_eq!(&read[..], &data[..]);
+
+        nor.erase_sector(0x1000).unwrap();
+        nor.read(0x0FF0, &mut read).unwrap();
+        assert_eq!(&read[..16], &data[..16]);
+        assert!(read[16..].iter().all(|b| *b == 0xFF));
+
+        nor.erase_block(0).unwrap();
+        nor.read(0x0FF0, &mut read).unwrap();
+        assert!(read.iter().all(|b| *b == 0xFF));
+
+        nor.program(0, &[0x00]).unwrap();
+        nor.erase_chip().unwrap();
+        nor.read(0, &mut read[..1]).unwrap();
+        assert_eq!(read[0], 0xFF);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut nor = Nor::new(Emulated::new()).unwrap();
+        let mut read = [0; 2];
+
+        assert_eq!(
+            nor.read(SIZE as u32 - 1, &mut read),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(nor.program(SIZE as u32, &[0]), Err(Error::OutOfBounds));
+        assert_eq!(nor.erase_sector(0x800), Err(Error::NotAligned));
+        assert_eq!(nor.erase_block(SIZE as u32), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn power_down() {
+        let mut nor = Nor::new(Emulated::new()).unwrap();
+        nor.program(0, &[0x5A]).unwrap();
+
+        nor.power_down().unwrap();
+        let mut read = [0];
+        nor.read(0, &mut read).unwrap();
+        assert_eq!(read[0], 0xFF);
+
+        nor.release_power_down().unwrap();
+        nor.read(0, &mut read).unwrap();
+        assert_eq!(read[0], 0x5A);
+    }
+}
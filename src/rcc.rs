@@ -246,6 +246,15 @@ pub struct APB1R1 {
 }
 
 impl APB1R1 {
+    /// Creates a second proxy, for drivers that reset their peripheral from an interrupt handler
+    ///
+    /// # Safety
+    ///
+    /// The registers must only be modified in a critical section.
+    pub(crate) unsafe fn steal() -> Self {
+        APB1R1 { _0: () }
+    }
+
     pub(crate) fn enr(&mut self) -> &rcc::APB1ENR1 {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { &(*RCC::ptr()).apb1enr1 }
@@ -295,6 +304,15 @@ pub struct APB2 {
 }
 
 impl APB2 {
+    /// Creates a second proxy, for drivers that reset their peripheral from an interrupt handler
+    ///
+    /// # Safety
+    ///
+    /// The registers must only be modified in a critical section.
+    pub(crate) unsafe fn steal() -> Self {
+        APB2 { _0: () }
+    }
+
     pub(crate) fn enr(&mut self) -> &rcc::APB2ENR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { &(*RCC::ptr()).apb2enr }
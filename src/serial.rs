@@ -11,6 +11,7 @@ use embedded_dma::StaticWriteBuffer;
 use stable_deref_trait::StableDeref;
 
 use crate::hal::serial::{self, Write};
+use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
 
 use crate::dma::{
     dma1, CircBuffer, DMAFrame, FrameReader, FrameSender, Receive, RxDma, TransferPayload,
@@ -19,7 +20,7 @@ use crate::dma::{
 use crate::gpio::{self, Alternate, AlternateOD, Floating, Input};
 use crate::pac;
 use crate::rcc::{Clocks, APB1R1, APB2};
-use crate::time::{Bps, U32Ext};
+use crate::time::{Bps, Hertz, U32Ext};
 
 #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6",))]
 use crate::dma::dma2;
@@ -36,6 +37,8 @@ pub enum Event {
     CharacterMatch,
     /// Receiver timeout
     ReceiverTimeout,
+    /// LIN break detected
+    LinBreak,
 }
 
 /// Serial error
@@ -50,6 +53,8 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// Automatic baud rate detection failed
+    AutoBaudRate,
 }
 
 /// USART parity settings
@@ -62,6 +67,16 @@ pub enum Parity {
     ParityOdd,
 }
 
+/// USART word length settings, not including the parity bit
+pub enum WordLength {
+    /// 7 data bits
+    DataBits7,
+    /// 8 data bits
+    DataBits8,
+    /// 9 data bits
+    DataBits9,
+}
+
 /// USART stopbits settings
 pub enum StopBits {
     /// 1 stop bit
@@ -82,16 +97,157 @@ pub enum Oversampling {
     Over16,
 }
 
+/// Automatic baud rate detection method
+pub enum AutoBaudMode {
+    /// Measure the start bit, the character must start with a 1 bit
+    StartBit,
+    /// Measure from falling edge to falling edge, the character must start with a `10` bit
+    /// pattern
+    FallingEdge,
+    /// Detect a 0x7F frame
+    Frame0x7F,
+    /// Detect a 0x55 frame
+    Frame0x55,
+}
+
+/// LIN break detection length
+pub enum LinBreakLength {
+    /// 10-bit break detection
+    Bits10,
+    /// 11-bit break detection
+    Bits11,
+}
+
+/// IrDA SIR power mode
+pub enum IrdaMode {
+    /// Normal mode, pulses are 3/16 of a bit duration
+    Normal,
+    /// Low-power mode, pulses are 3 periods of the low-power frequency (nominally 1.8432 MHz)
+    LowPower,
+}
+
+/// Smartcard (ISO 7816) configuration structure
+pub struct SmartcardConfig {
+    clock: Hertz,
+    clocks_per_etu: u16,
+    guard_time: u8,
+    nack: bool,
+    retries: u8,
+}
+
+impl SmartcardConfig {
+    /// Set the frequency of the clock provided to the card on the CK pin
+    ///
+    /// The actual frequency is the closest one not above `clock` that can be derived from the
+    /// peripheral clock.
+    pub fn clock(mut self, clock: Hertz) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the number of card clock cycles per elementary time unit (bit)
+    ///
+    /// Defaults to 372, the value used for the answer to reset.
+    pub fn clocks_per_etu(mut self, clocks_per_etu: u16) -> Self {
+        self.clocks_per_etu = clocks_per_etu;
+        self
+    }
+
+    /// Set the guard time, in bit durations
+    pub fn guard_time(mut self, guard_time: u8) -> Self {
+        self.guard_time = guard_time;
+        self
+    }
+
+    /// Enable or disable sending a NACK on parity errors
+    pub fn nack(mut self, nack: bool) -> Self {
+        self.nack = nack;
+        self
+    }
+
+    /// Set the number of automatic retransmissions after a NACK
+    ///
+    /// Note that it only takes 3 bits, using more than this will cause a panic.
+    pub fn retries(mut self, retries: u8) -> Self {
+        assert!(retries < 1 << 3);
+        self.retries = retries;
+        self
+    }
+}
+
+impl Default for SmartcardConfig {
+    fn default() -> SmartcardConfig {
+        SmartcardConfig {
+            clock: Hertz(3_571_200),
+            clocks_per_etu: 372,
+            guard_time: 16,
+            nack: true,
+            retries: 3,
+        }
+    }
+}
+
+/// RS485 driver enable polarity
+pub enum DePolarity {
+    /// DE is asserted high
+    ActiveHigh,
+    /// DE is asserted low
+    ActiveLow,
+}
+
+/// Address length for address mark detection
+pub enum AddressLength {
+    /// The 4 LSBs of an address character are compared
+    Bits4,
+    /// The 7 LSBs of an address character are compared
+    Bits7,
+}
+
+/// Mute mode (multiprocessor communication) settings
+pub enum MuteMode {
+    /// The receiver wakes up from mute mode when the line goes idle
+    IdleLine,
+    /// The receiver wakes up from mute mode when an address character (MSB set) matching
+    /// `address` is received
+    AddressMark {
+        /// Address of this node
+        address: u8,
+        /// Number of address bits that are compared
+        length: AddressLength,
+    },
+}
+
+/// Receiver timeout settings
+enum ReceiverTimeout {
+    /// Timeout in bit durations
+    Bits(u32),
+    /// Timeout in half character durations
+    HalfCharacters(u32),
+    /// Modbus RTU end of frame timeout
+    ModbusRtu,
+}
+
 /// USART Configuration structure
 pub struct Config {
     baudrate: Bps,
+    word_length: WordLength,
     parity: Parity,
     stopbits: StopBits,
     oversampling: Oversampling,
     character_match: Option<u8>,
-    receiver_timeout: Option<u32>,
+    receiver_timeout: Option<ReceiverTimeout>,
     disable_overrun: bool,
     onebit_sampling: bool,
+    de_assertion_time: u8,
+    de_deassertion_time: u8,
+    de_polarity: DePolarity,
+    mute_mode: Option<MuteMode>,
+    swap: bool,
+    invert_data: bool,
+    invert_tx: bool,
+    invert_rx: bool,
+    msb_first: bool,
+    auto_baud: Option<AutoBaudMode>,
 }
 
 impl Config {
@@ -101,6 +257,14 @@ impl Config {
         self
     }
 
+    /// Set the number of data bits
+    ///
+    /// Note that 9 data bits cannot be combined with parity.
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
     /// Set parity to none
     pub fn parity_none(mut self) -> Self {
         self.parity = Parity::ParityNone;
@@ -142,7 +306,35 @@ impl Config {
     /// Note that it only takes 24 bits, using more than this will cause a panic.
     pub fn receiver_timeout(mut self, receiver_timeout: u32) -> Self {
         assert!(receiver_timeout < 1 << 24);
-        self.receiver_timeout = Some(receiver_timeout);
+        self.receiver_timeout = Some(ReceiverTimeout::Bits(receiver_timeout));
+        self
+    }
+
+    /// Set the receiver timeout as a number of character durations
+    ///
+    /// The duration of a character is derived from the start, data, parity and stop bits. The
+    /// timeout is saturated to the largest timeout of the USART, 2^24 - 1 bit durations.
+    pub fn receiver_timeout_characters(mut self, characters: u32) -> Self {
+        self.receiver_timeout = Some(ReceiverTimeout::HalfCharacters(
+            characters.saturating_mul(2),
+        ));
+        self
+    }
+
+    /// Set the receiver timeout as a number of half character durations, e.g. `7` for 3.5
+    /// characters
+    ///
+    /// The timeout is saturated to the largest timeout of the USART, 2^24 - 1 bit durations.
+    pub fn receiver_timeout_half_characters(mut self, half_characters: u32) -> Self {
+        self.receiver_timeout = Some(ReceiverTimeout::HalfCharacters(half_characters));
+        self
+    }
+
+    /// Set the receiver timeout to the Modbus RTU end of frame silence
+    ///
+    /// This is 3.5 character durations up to 19200 bps, and a fixed 1.75 ms above that.
+    pub fn receiver_timeout_modbus_rtu(mut self) -> Self {
+        self.receiver_timeout = Some(ReceiverTimeout::ModbusRtu);
         self
     }
 
@@ -157,6 +349,134 @@ impl Config {
         self.onebit_sampling = true;
         self
     }
+
+    /// Swap the TX and RX pin functions
+    pub fn with_swapped_pins(mut self) -> Self {
+        self.swap = true;
+        self
+    }
+
+    /// Invert the data bits (1 is sent as a low level and 0 as a high level), including the
+    /// parity bit
+    pub fn with_inverted_data(mut self) -> Self {
+        self.invert_data = true;
+        self
+    }
+
+    /// Invert the TX pin level, idle becomes low
+    pub fn with_inverted_tx(mut self) -> Self {
+        self.invert_tx = true;
+        self
+    }
+
+    /// Invert the RX pin level, idle becomes low
+    pub fn with_inverted_rx(mut self) -> Self {
+        self.invert_rx = true;
+        self
+    }
+
+    /// Send and receive the most significant bit first
+    pub fn with_msb_first(mut self) -> Self {
+        self.msb_first = true;
+        self
+    }
+
+    /// Enable automatic baud rate detection on the first received character
+    ///
+    /// The configured baud rate is used until detection completes, see
+    /// `Serial::check_auto_baud_rate`.
+    pub fn auto_baud(mut self, mode: AutoBaudMode) -> Self {
+        self.auto_baud = Some(mode);
+        self
+    }
+
+    /// Set the RS485 driver enable assertion time, in sample time units (1/8 or 1/16 of a bit
+    /// depending on the oversampling)
+    ///
+    /// Note that it only takes 5 bits, using more than this will cause a panic.
+    pub fn de_assertion_time(mut self, time: u8) -> Self {
+        assert!(time < 1 << 5);
+        self.de_assertion_time = time;
+        self
+    }
+
+    /// Set the RS485 driver enable deassertion time, in sample time units (1/8 or 1/16 of a
+    /// bit depending on the oversampling)
+    ///
+    /// Note that it only takes 5 bits, using more than this will cause a panic.
+    pub fn de_deassertion_time(mut self, time: u8) -> Self {
+        assert!(time < 1 << 5);
+        self.de_deassertion_time = time;
+        self
+    }
+
+    /// Set the RS485 driver enable polarity
+    pub fn de_polarity(mut self, polarity: DePolarity) -> Self {
+        self.de_polarity = polarity;
+        self
+    }
+
+    /// Enable mute mode for multiprocessor communication
+    ///
+    /// Note that address mark detection and character match share the same address register,
+    /// so they cannot be used together.
+    pub fn mute_mode(mut self, mute_mode: MuteMode) -> Self {
+        self.mute_mode = Some(mute_mode);
+        self
+    }
+
+    /// Number of half bit durations in one character, including start, parity and stop bits
+    fn character_half_bits(&self) -> u32 {
+        let (m1, m0) = self.word_length_bits();
+        let data_bits = match (m1, m0) {
+            (true, _) => 7,
+            (false, false) => 8,
+            (false, true) => 9,
+        };
+        let stop_half_bits = match self.stopbits {
+            StopBits::STOP0P5 => 1,
+            StopBits::STOP1 => 2,
+            StopBits::STOP1P5 => 3,
+            StopBits::STOP2 => 4,
+        };
+
+        2 * (1 + data_bits) + stop_half_bits
+    }
+
+    /// The M1 and M0 word length bits
+    ///
+    /// Unlike most uart devices, the "word length" of this usart device refers to the size of the
+    /// data plus the parity bit. I.e. "word length"=8, parity=even results in 7 bits of data.
+    /// Therefore, in order to get 8 bits and one parity bit, we need to set the "word" length to
+    /// 9 when using parity bits.
+    fn word_length_bits(&self) -> (bool, bool) {
+        let parity = !matches!(self.parity, Parity::ParityNone);
+
+        match (&self.word_length, parity) {
+            (WordLength::DataBits7, false) => (true, false),
+            (WordLength::DataBits7, true) | (WordLength::DataBits8, false) => (false, false),
+            (WordLength::DataBits8, true) | (WordLength::DataBits9, false) => (false, true),
+            (WordLength::DataBits9, true) => panic!("9 data bits cannot be combined with parity"),
+        }
+    }
+
+    /// The receiver timeout in bit durations
+    fn receiver_timeout_bits(&self) -> Option<u32> {
+        let half_characters = match self.receiver_timeout.as_ref()? {
+            ReceiverTimeout::Bits(bits) => return Some(*bits),
+            ReceiverTimeout::HalfCharacters(half_characters) => *half_characters,
+            ReceiverTimeout::ModbusRtu if self.baudrate.0 > 19_200 => {
+                // Fixed 1750 us, rounded up to whole bits
+                return Some((1750 * self.baudrate.0 as u64).div_ceil(1_000_000) as u32);
+            }
+            ReceiverTimeout::ModbusRtu => 7,
+        };
+
+        // Round up to whole bits, the product does not fit in 32 bits for long timeouts
+        let bits = (half_characters as u64 * self.character_half_bits() as u64).div_ceil(4);
+
+        Some(bits.min((1 << 24) - 1) as u32)
+    }
 }
 
 impl Default for Config {
@@ -164,6 +484,7 @@ impl Default for Config {
         let baudrate = 115_200_u32.bps();
         Config {
             baudrate,
+            word_length: WordLength::DataBits8,
             parity: Parity::ParityNone,
             stopbits: StopBits::STOP1,
             oversampling: Oversampling::Over16,
@@ -171,6 +492,16 @@ impl Default for Config {
             receiver_timeout: None,
             disable_overrun: false,
             onebit_sampling: false,
+            de_assertion_time: 0b1111,
+            de_deassertion_time: 0b1111,
+            de_polarity: DePolarity::ActiveHigh,
+            mute_mode: None,
+            swap: false,
+            invert_data: false,
+            invert_tx: false,
+            invert_rx: false,
+            msb_first: false,
+            auto_baud: None,
         }
     }
 }
@@ -191,11 +522,153 @@ pub struct Tx<USART> {
     _usart: PhantomData<USART>,
 }
 
+/// Serial interface transferring 9-bit words
+///
+/// Created through `Serial::into_9bit`, the `Serial` must be configured with
+/// [`WordLength::DataBits9`].
+pub struct Serial9Bit<USART, PINS> {
+    serial: Serial<USART, PINS>,
+}
+
+/// Fixed capacity byte FIFO over a user supplied buffer
+///
+/// Used by [`BufferedSerial`] to hold bytes in flight between the USART interrupt and the
+/// application.
+pub struct RingBuffer {
+    buffer: &'static mut [u8],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    /// Creates an empty ring buffer backed by `buffer`
+    pub fn new(buffer: &'static mut [u8]) -> Self {
+        RingBuffer {
+            buffer,
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Maximum number of bytes the ring buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of bytes currently stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no bytes are stored
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if no more bytes can be stored
+    pub fn is_full(&self) -> bool {
+        self.len == self.buffer.len()
+    }
+
+    /// Number of bytes that can still be stored
+    pub fn free(&self) -> usize {
+        self.buffer.len() - self.len
+    }
+
+    /// Discards all stored bytes
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Appends a byte, handing it back if the buffer is full
+    pub fn push(&mut self, byte: u8) -> Result<(), u8> {
+        if self.is_full() {
+            return Err(byte);
+        }
+
+        let tail = (self.head + self.len) % self.buffer.len();
+        self.buffer[tail] = byte;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Removes the oldest byte
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % self.buffer.len();
+        self.len -= 1;
+
+        Some(byte)
+    }
+
+    /// Appends as many bytes of `data` as fit, returning how many were stored
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(self.free());
+        let tail = (self.head + self.len) % self.buffer.len().max(1);
+
+        // Copy in at most two chunks, up to the end of the buffer and then from the start
+        let first = count.min(self.buffer.len() - tail);
+        self.buffer[tail..tail + first].copy_from_slice(&data[..first]);
+        self.buffer[..count - first].copy_from_slice(&data[first..count]);
+        self.len += count;
+
+        count
+    }
+
+    /// Moves as many of the oldest bytes as fit into `data`, returning how many were read
+    pub fn read(&mut self, data: &mut [u8]) -> usize {
+        let count = data.len().min(self.len);
+
+        let first = count.min(self.buffer.len() - self.head);
+        data[..first].copy_from_slice(&self.buffer[self.head..self.head + first]);
+        data[first..count].copy_from_slice(&self.buffer[..count - first]);
+        self.head = (self.head + count) % self.buffer.len().max(1);
+        self.len -= count;
+
+        count
+    }
+}
+
+/// Error and overrun counters kept by [`BufferedSerial`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferedStats {
+    /// Bytes lost because the USART receive register was overrun
+    pub overrun: u32,
+    /// Bytes dropped because the receive ring buffer was full
+    pub rx_dropped: u32,
+    /// Framing errors
+    pub framing: u32,
+    /// Noise errors
+    pub noise: u32,
+    /// Parity errors
+    pub parity: u32,
+}
+
+/// Interrupt driven serial interface with receive and transmit ring buffers
+///
+/// Created through `Serial::into_buffered`. The USART interrupt handler must call
+/// `on_interrupt`, which fills the receive buffer and drains the transmit buffer. As both the
+/// interrupt and the application need `&mut` access, the `BufferedSerial` is typically shared
+/// as an RTIC resource or through a `cortex_m::interrupt::Mutex`.
+pub struct BufferedSerial<USART, PINS> {
+    serial: Serial<USART, PINS>,
+    rx: RingBuffer,
+    tx: RingBuffer,
+    stats: BufferedStats,
+}
+
 macro_rules! hal {
     ($(
         $(#[$meta:meta])*
         $USARTX:ident: (
             $usartX:ident,
+            modes: ($usartXlin:ident, $usartXirda:ident),
             $APB:ident,
             $usartXen:ident,
             $usartXrst:ident,
@@ -231,6 +704,30 @@ macro_rules! hal {
                 where
                     PINS: Pins<pac::$USARTX>,
                 {
+                    Self::configure(
+                        &usart,
+                        &config,
+                        clocks,
+                        apb,
+                        PINS::FLOWCTL,
+                        PINS::DEM,
+                        PINS::HALF_DUPLEX,
+                    );
+                    Self::enable(&usart);
+
+                    Serial { usart, pins }
+                }
+
+                /// Resets the USART and applies `config`, leaving the USART disabled
+                fn configure(
+                    usart: &pac::$USARTX,
+                    config: &Config,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                    flowctl: bool,
+                    dem: bool,
+                    half_duplex: bool,
+                ) {
                     // enable or reset $USARTX
                     apb.enr().modify(|_, w| w.$usartXen().set_bit());
                     apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
@@ -261,7 +758,8 @@ macro_rules! hal {
                         }
                     }
 
-                    if let Some(val) = config.receiver_timeout {
+                    let receiver_timeout = config.receiver_timeout_bits();
+                    if let Some(val) = receiver_timeout {
                         usart.rtor.modify(|_, w| w.rto().bits(val));
                     }
 
@@ -269,13 +767,23 @@ macro_rules! hal {
                     usart.cr3.modify(|_, w| w.dmat().set_bit().dmar().set_bit());
 
                     // Configure hardware flow control (CTS/RTS or RS485 Driver Enable)
-                    if PINS::FLOWCTL {
+                    if flowctl {
                         usart.cr3.modify(|_, w| w.rtse().set_bit().ctse().set_bit());
-                    } else if PINS::DEM {
-                        usart.cr3.modify(|_, w| w.dem().set_bit());
+                    } else if dem {
+                        usart.cr3.modify(|_, w| {
+                            w.dem().set_bit().dep().bit(match config.de_polarity {
+                                DePolarity::ActiveHigh => false,
+                                DePolarity::ActiveLow => true,
+                            })
+                        });
 
-                        // Pre/post driver enable set conservative to the max time
-                        usart.cr1.modify(|_, w| w.deat().bits(0b1111).dedt().bits(0b1111));
+                        // Pre/post driver enable times, conservative to the max time by default
+                        usart.cr1.modify(|_, w| {
+                            w.deat()
+                                .bits(config.de_assertion_time)
+                                .dedt()
+                                .bits(config.de_deassertion_time)
+                        });
                     } else {
                         usart.cr3.modify(|_, w| w.rtse().clear_bit().ctse().clear_bit());
                     }
@@ -291,7 +799,7 @@ macro_rules! hal {
                         }
 
                         // configure Half Duplex
-                        if PINS::HALF_DUPLEX {
+                        if half_duplex {
                             w.hdsel().set_bit();
                         }
 
@@ -299,18 +807,16 @@ macro_rules! hal {
                     });
 
                     // Configure parity and word length
-                    // Unlike most uart devices, the "word length" of this usart device refers to
-                    // the size of the data plus the parity bit. I.e. "word length"=8, parity=even
-                    // results in 7 bits of data. Therefore, in order to get 8 bits and one parity
-                    // bit, we need to set the "word" length to 9 when using parity bits.
-                    let (word_length, parity_control_enable, parity) = match config.parity {
-                        Parity::ParityNone => (false, false, false),
-                        Parity::ParityEven => (true, true, false),
-                        Parity::ParityOdd => (true, true, true),
+                    let (m1, m0) = config.word_length_bits();
+                    let (parity_control_enable, parity) = match config.parity {
+                        Parity::ParityNone => (false, false),
+                        Parity::ParityEven => (true, false),
+                        Parity::ParityOdd => (true, true),
                     };
                     usart.cr1.modify(|_r, w| {
                         w
-                            .m0().bit(word_length)
+                            .m1().bit(m1)
+                            .m0().bit(m0)
                             .ps().bit(parity)
                             .pce().bit(parity_control_enable)
                     });
@@ -330,24 +836,164 @@ macro_rules! hal {
                             w.add().bits(c);
                         }
 
-                        if config.receiver_timeout.is_some() {
+                        if receiver_timeout.is_some() {
                             w.rtoen().set_bit();
                         }
 
+                        // Setup pin swapping, inversion and bit order
+                        w.swap()
+                            .bit(config.swap)
+                            .datainv()
+                            .bit(config.invert_data)
+                            .txinv()
+                            .bit(config.invert_tx)
+                            .rxinv()
+                            .bit(config.invert_rx)
+                            .msbfirst()
+                            .bit(config.msb_first);
+
+                        // Setup automatic baud rate detection (if requested)
+                        if let Some(mode) = &config.auto_baud {
+                            w.abren().set_bit().abrmod().bits(match mode {
+                                AutoBaudMode::StartBit => 0b00,
+                                AutoBaudMode::FallingEdge => 0b01,
+                                AutoBaudMode::Frame0x7F => 0b10,
+                                AutoBaudMode::Frame0x55 => 0b11,
+                            });
+                        }
+
+                        // Setup address mark detection (if requested)
+                        if let Some(MuteMode::AddressMark { address, length }) = &config.mute_mode {
+                            assert!(
+                                config.character_match.is_none(),
+                                "address mark detection and character match are exclusive"
+                            );
+
+                            match length {
+                                AddressLength::Bits4 => {
+                                    assert!(*address < 1 << 4, "4-bit address out of range");
+                                    w.addm7().clear_bit();
+                                }
+                                AddressLength::Bits7 => {
+                                    assert!(*address < 1 << 7, "7-bit address out of range");
+                                    w.addm7().set_bit();
+                                }
+                            }
+                            w.add().bits(*address);
+                        }
+
                         w
                     });
 
+                    // Configure mute mode and its wakeup method
+                    match config.mute_mode {
+                        Some(MuteMode::IdleLine) => {
+                            usart.cr1.modify(|_, w| w.mme().set_bit().wake().clear_bit());
+                        }
+                        Some(MuteMode::AddressMark { .. }) => {
+                            usart.cr1.modify(|_, w| w.mme().set_bit().wake().set_bit());
+                        }
+                        None => {}
+                    }
+                }
 
+                /// Enables the USART, its receiver and its transmitter
+                fn enable(usart: &pac::$USARTX) {
                     // UE: enable USART
                     // RE: enable receiver
                     // TE: enable transceiver
                     usart
                         .cr1
                         .modify(|_, w| w.ue().set_bit().re().set_bit().te().set_bit());
+                }
+            }
+
+            impl<TX, RX> Serial<pac::$USARTX, (TX, RX)>
+            where
+                TX: TxPin<pac::$USARTX>,
+                RX: RxPin<pac::$USARTX>,
+            {
+                /// Configures the serial interface for LIN communication
+                ///
+                /// LIN requires 8 data bits without parity and 1 stop bit. Breaks are sent with
+                /// [`Serial::send_break`], detected breaks raise [`Event::LinBreak`] and can be
+                /// checked with [`Rx::is_lin_break`].
+                pub fn $usartXlin(
+                    usart: pac::$USARTX,
+                    pins: (TX, RX),
+                    config: Config,
+                    break_length: LinBreakLength,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self {
+                    assert!(
+                        matches!(config.word_length, WordLength::DataBits8),
+                        "LIN mode requires 8 data bits"
+                    );
+                    assert!(
+                        matches!(config.parity, Parity::ParityNone),
+                        "LIN mode does not support parity"
+                    );
+                    assert!(
+                        matches!(config.stopbits, StopBits::STOP1),
+                        "LIN mode requires 1 stop bit"
+                    );
+
+                    Self::configure(&usart, &config, clocks, apb, false, false, false);
+
+                    usart.cr2.modify(|_, w| {
+                        w.linen().set_bit().lbdl().bit(match break_length {
+                            LinBreakLength::Bits10 => false,
+                            LinBreakLength::Bits11 => true,
+                        })
+                    });
+
+                    Self::enable(&usart);
 
                     Serial { usart, pins }
                 }
 
+                /// Configures the serial interface for IrDA SIR communication
+                ///
+                /// The pins are connected to an infrared transceiver, IrDA SIR requires 1 stop
+                /// bit and at most 115200 bps.
+                pub fn $usartXirda(
+                    usart: pac::$USARTX,
+                    pins: (TX, RX),
+                    config: Config,
+                    mode: IrdaMode,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self {
+                    assert!(
+                        matches!(config.stopbits, StopBits::STOP1),
+                        "IrDA mode requires 1 stop bit"
+                    );
+                    assert!(config.baudrate.0 <= 115_200, "IrDA SIR is limited to 115200 bps");
+
+                    Self::configure(&usart, &config, clocks, apb, false, false, false);
+
+                    let (low_power, psc) = match mode {
+                        IrdaMode::Normal => (false, 1),
+                        IrdaMode::LowPower => {
+                            // Divide down to the nominal low-power frequency of 1.8432 MHz
+                            let psc = (clocks.$pclkX().0 / 1_843_200).max(1);
+                            assert!(psc <= 0xff, "impossible IrDA low-power frequency");
+                            (true, psc as u8)
+                        }
+                    };
+
+                    usart.gtpr.modify(|_, w| w.psc().bits(psc));
+                    usart.cr3.modify(|_, w| w.iren().set_bit().irlp().bit(low_power));
+
+                    Self::enable(&usart);
+
+                    Serial { usart, pins }
+                }
+            }
+
+            impl<PINS> Serial<pac::$USARTX, PINS> {
+
                 /// Starts listening for an interrupt event
                 pub fn listen(&mut self, event: Event) {
                     match event {
@@ -366,6 +1012,9 @@ macro_rules! hal {
                         Event::ReceiverTimeout => {
                             self.usart.cr1.modify(|_, w| w.rtoie().set_bit())
                         },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().set_bit())
+                        },
                     }
                 }
 
@@ -397,9 +1046,50 @@ macro_rules! hal {
                         Event::ReceiverTimeout => {
                             self.usart.cr1.modify(|_, w| w.rtoie().clear_bit())
                         },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().clear_bit())
+                        },
+                    }
+                }
+
+                /// Sends a break character once the current transmission is complete
+                pub fn send_break(&mut self) {
+                    self.usart.rqr.write(|w| w.sbkrq().set_bit());
+                }
+
+                /// Checks whether automatic baud rate detection has completed
+                ///
+                /// Returns `Error::AutoBaudRate` if the detection failed, in which case it can be
+                /// restarted with [`Serial::request_auto_baud_rate`].
+                pub fn check_auto_baud_rate(&self) -> nb::Result<(), Error> {
+                    let isr = self.usart.isr.read();
+
+                    if isr.abre().bit_is_set() {
+                        Err(nb::Error::Other(Error::AutoBaudRate))
+                    } else if isr.abrf().bit_is_set() {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
                     }
                 }
 
+                /// Restarts automatic baud rate detection on the next received character
+                pub fn request_auto_baud_rate(&mut self) {
+                    self.usart.rqr.write(|w| w.abrrq().set_bit());
+                }
+
+                /// Requests the receiver to enter mute mode
+                ///
+                /// Only has an effect if mute mode was enabled through [`Config::mute_mode`].
+                pub fn enter_mute_mode(&mut self) {
+                    self.usart.rqr.write(|w| w.mmrq().set_bit());
+                }
+
+                /// Returns `true` if the receiver is in mute mode
+                pub fn is_muted(&self) -> bool {
+                    self.usart.isr.read().rwu().bit_is_set()
+                }
+
                 /// Splits the `Serial` abstraction into a transmitter and a receiver half
                 pub fn split(self) -> (Tx<pac::$USARTX>, Rx<pac::$USARTX>) {
                     (
@@ -468,10 +1158,10 @@ macro_rules! hal {
             }
 
             impl serial::Write<u8> for Tx<pac::$USARTX> {
-                // NOTE(Void) See section "29.7 USART interrupts"; the only possible errors during
+                // NOTE See section "29.7 USART interrupts"; the only possible errors during
                 // transmission are: clear to send (which is disabled in this case) errors and
-                // framing errors (which only occur in SmartCard mode); neither of these apply to
-                // our hardware configuration
+                // framing errors (which only occur in SmartCard mode, when the card NACKs a
+                // character after all retries). The latter are reported by `Rx::check_for_error`.
                 type Error = Error;
 
                 fn flush(&mut self) -> nb::Result<(), Error> {
@@ -505,6 +1195,204 @@ macro_rules! hal {
             impl embedded_hal::blocking::serial::write::Default<u8>
                 for Tx<pac::$USARTX> {}
 
+            impl<PINS> Serial<pac::$USARTX, PINS> {
+                /// Converts the serial interface into one transferring 9-bit words, intended for
+                /// use with [`WordLength::DataBits9`]
+                pub fn into_9bit(self) -> Serial9Bit<pac::$USARTX, PINS> {
+                    Serial9Bit { serial: self }
+                }
+            }
+
+            impl<PINS> Serial9Bit<pac::$USARTX, PINS> {
+                /// Converts back into a serial interface transferring bytes
+                pub fn into_8bit(self) -> Serial<pac::$USARTX, PINS> {
+                    self.serial
+                }
+            }
+
+            impl<PINS> serial::Read<u16> for Serial9Bit<pac::$USARTX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    Serial::<pac::$USARTX, PINS>::check_for_error()?;
+
+                    let usart = &self.serial.usart;
+                    if usart.isr.read().rxne().bit_is_set() {
+                        return Ok(usart.rdr.read().rdr().bits());
+                    }
+
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+
+            impl<PINS> serial::Write<u16> for Serial9Bit<pac::$USARTX, PINS> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Error> {
+                    self.serial.flush()
+                }
+
+                fn write(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let usart = &self.serial.usart;
+                    if usart.isr.read().txe().bit_is_set() {
+                        // NOTE(unsafe) atomic write to stateless register
+                        usart.tdr.write(|w| unsafe { w.tdr().bits(word) });
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<PINS> embedded_hal::blocking::serial::write::Default<u16>
+                for Serial9Bit<pac::$USARTX, PINS> {}
+
+            impl<PINS> Serial<pac::$USARTX, PINS> {
+                /// Converts the serial interface into an interrupt driven one using `rx_buffer`
+                /// and `tx_buffer` as receive and transmit ring buffers
+                ///
+                /// This enables the RXNE interrupt, the transmit interrupt is enabled on demand.
+                pub fn into_buffered(
+                    mut self,
+                    rx_buffer: &'static mut [u8],
+                    tx_buffer: &'static mut [u8],
+                ) -> BufferedSerial<pac::$USARTX, PINS> {
+                    self.listen(Event::Rxne);
+
+                    BufferedSerial {
+                        serial: self,
+                        rx: RingBuffer::new(rx_buffer),
+                        tx: RingBuffer::new(tx_buffer),
+                        stats: BufferedStats::default(),
+                    }
+                }
+            }
+
+            impl<PINS> BufferedSerial<pac::$USARTX, PINS> {
+                /// Services the USART, must be called from the USART interrupt handler
+                pub fn on_interrupt(&mut self) {
+                    let usart = &self.serial.usart;
+                    let isr = usart.isr.read();
+
+                    if isr.pe().bit_is_set() {
+                        usart.icr.write(|w| w.pecf().clear());
+                        self.stats.parity = self.stats.parity.wrapping_add(1);
+                    }
+                    if isr.fe().bit_is_set() {
+                        usart.icr.write(|w| w.fecf().clear());
+                        self.stats.framing = self.stats.framing.wrapping_add(1);
+                    }
+                    if isr.nf().bit_is_set() {
+                        usart.icr.write(|w| w.ncf().clear());
+                        self.stats.noise = self.stats.noise.wrapping_add(1);
+                    }
+                    if isr.ore().bit_is_set() {
+                        usart.icr.write(|w| w.orecf().clear());
+                        self.stats.overrun = self.stats.overrun.wrapping_add(1);
+                    }
+
+                    if isr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) see `Rx::read`
+                        let byte = unsafe {
+                            ptr::read_volatile(&usart.rdr as *const _ as *const u8)
+                        };
+                        if self.rx.push(byte).is_err() {
+                            self.stats.rx_dropped = self.stats.rx_dropped.wrapping_add(1);
+                        }
+                    }
+
+                    if isr.txe().bit_is_set() && usart.cr1.read().txeie().bit_is_set() {
+                        match self.tx.pop() {
+                            // NOTE(write_volatile) see `Tx::write`
+                            Some(byte) => unsafe {
+                                ptr::write_volatile(&usart.tdr as *const _ as *mut u8, byte)
+                            },
+                            None => usart.cr1.modify(|_, w| w.txeie().clear_bit()),
+                        }
+                    }
+                }
+
+                /// Reads received bytes into `buffer`, returning the number of bytes read
+                pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+                    self.rx.read(buffer)
+                }
+
+                /// Queues bytes from `buffer` for transmission, returning the number of bytes
+                /// queued
+                pub fn write(&mut self, buffer: &[u8]) -> usize {
+                    let count = self.tx.write(buffer);
+
+                    if !self.tx.is_empty() {
+                        self.serial.usart.cr1.modify(|_, w| w.txeie().set_bit());
+                    }
+
+                    count
+                }
+
+                /// Completes once all queued bytes have been sent out on the line
+                pub fn flush(&mut self) -> nb::Result<(), Error> {
+                    if self.tx.is_empty() && self.serial.usart.isr.read().tc().bit_is_set() {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Number of received bytes waiting to be read
+                pub fn rx_len(&self) -> usize {
+                    self.rx.len()
+                }
+
+                /// Number of bytes that can still be queued for transmission
+                pub fn tx_free(&self) -> usize {
+                    self.tx.free()
+                }
+
+                /// Error and overrun counters since creation or the last `clear_stats`
+                pub fn stats(&self) -> BufferedStats {
+                    self.stats
+                }
+
+                /// Resets the error and overrun counters
+                pub fn clear_stats(&mut self) {
+                    self.stats = BufferedStats::default();
+                }
+
+                /// Stops the interrupts and releases the serial interface and the buffers
+                ///
+                /// Bytes still queued for transmission are discarded.
+                pub fn release(mut self) -> (Serial<pac::$USARTX, PINS>, &'static mut [u8], &'static mut [u8]) {
+                    self.serial.unlisten(Event::Rxne);
+                    self.serial.unlisten(Event::Txe);
+
+                    (self.serial, self.rx.buffer, self.tx.buffer)
+                }
+            }
+
+            impl<PINS> serial::Read<u8> for BufferedSerial<pac::$USARTX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.rx.pop().ok_or(nb::Error::WouldBlock)
+                }
+            }
+
+            impl<PINS> serial::Write<u8> for BufferedSerial<pac::$USARTX, PINS> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Error> {
+                    BufferedSerial::<pac::$USARTX, PINS>::flush(self)
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    if BufferedSerial::<pac::$USARTX, PINS>::write(self, &[byte]) == 1 {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
             pub type $rxdma = RxDma<Rx<pac::$USARTX>, $dmarxch>;
             pub type $txdma = TxDma<Tx<pac::$USARTX>, $dmatxch>;
 
@@ -609,6 +1497,22 @@ macro_rules! hal {
                     }
                 }
 
+                /// Checks to see if the USART peripheral has detected a LIN break and clears
+                /// the flag
+                pub fn is_lin_break(&mut self, clear: bool) -> bool {
+                    let isr = unsafe { &(*pac::$USARTX::ptr()).isr.read() };
+                    let icr = unsafe { &(*pac::$USARTX::ptr()).icr };
+
+                    if isr.lbdf().bit_is_set() {
+                        if clear {
+                            icr.write(|w| w.lbdcf().set_bit() );
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                }
+
                 /// Checks to see if the USART peripheral has detected an character match and
                 /// clears the flag
                 pub fn check_character_match(&mut self, clear: bool) -> bool {
@@ -635,6 +1539,12 @@ macro_rules! hal {
             }
 
             impl Tx<pac::$USARTX> {
+                /// Sends a break character once the current transmission is complete
+                pub fn send_break(&mut self) {
+                    // NOTE(unsafe) atomic write to stateless register
+                    unsafe { (*pac::$USARTX::ptr()).rqr.write(|w| w.sbkrq().set_bit()) };
+                }
+
                 pub fn with_dma(self, channel: $dmatxch) -> $txdma {
                     TxDma {
                         payload: self,
@@ -814,8 +1724,8 @@ macro_rules! hal {
 }
 
 hal! {
-    USART1: (usart1, APB2, usart1en, usart1rst, pclk2, tx: (TxDma1, c4s, dma1::C4), rx: (RxDma1, c5s, dma1::C5)),
-    USART2: (usart2, APB1R1, usart2en, usart2rst, pclk1, tx: (TxDma2, c7s, dma1::C7), rx: (RxDma2, c6s, dma1::C6)),
+    USART1: (usart1, modes: (usart1_lin, usart1_irda), APB2, usart1en, usart1rst, pclk2, tx: (TxDma1, c4s, dma1::C4), rx: (RxDma1, c5s, dma1::C5)),
+    USART2: (usart2, modes: (usart2_lin, usart2_irda), APB1R1, usart2en, usart2rst, pclk1, tx: (TxDma2, c7s, dma1::C7), rx: (RxDma2, c6s, dma1::C6)),
 }
 
 #[cfg(any(
@@ -825,17 +1735,229 @@ hal! {
     feature = "stm32l4x6",
 ))]
 hal! {
-    USART3: (usart3, APB1R1, usart3en, usart3rst, pclk1, tx: (TxDma3, c2s, dma1::C2), rx: (RxDma3, c3s, dma1::C3)),
+    USART3: (usart3, modes: (usart3_lin, usart3_irda), APB1R1, usart3en, usart3rst, pclk1, tx: (TxDma3, c2s, dma1::C2), rx: (RxDma3, c3s, dma1::C3)),
 }
 
 #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6",))]
 hal! {
-    UART4: (uart4, APB1R1, uart4en, uart4rst, pclk1, tx: (TxDma4, c3s, dma2::C3), rx: (RxDma4, c5s, dma2::C5)),
+    UART4: (uart4, modes: (uart4_lin, uart4_irda), APB1R1, uart4en, uart4rst, pclk1, tx: (TxDma4, c3s, dma2::C3), rx: (RxDma4, c5s, dma2::C5)),
 }
 
 #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6",))]
 hal! {
-    UART5: (uart5, APB1R1, uart5en, uart5rst, pclk1, tx: (TxDma5, c1s, dma2::C1), rx: (RxDma5, c2s, dma2::C2)),
+    UART5: (uart5, modes: (uart5_lin, uart5_irda), APB1R1, uart5en, uart5rst, pclk1, tx: (TxDma5, c1s, dma2::C1), rx: (RxDma5, c2s, dma2::C2)),
+}
+
+/// BRR value of a bit lasting `clocks_per_etu` cycles of the card clock, divided by `2 * psc`
+fn smartcard_brr(psc: u32, clocks_per_etu: u16) -> u32 {
+    let brr = 2 * psc * clocks_per_etu as u32;
+    assert!(
+        (16..=0xFFFF).contains(&brr),
+        "impossible smartcard bit rate"
+    );
+
+    brr
+}
+
+macro_rules! smartcard {
+    ($(
+        $USARTX:ident: ($usartXsmartcard:ident, $APB:ident, $pclkX:ident),
+    )+) => {
+        $(
+            impl<TX, CK> Serial<pac::$USARTX, (TX, CK)>
+            where
+                TX: TxHalfDuplexPin<pac::$USARTX>,
+                CK: CkPin<pac::$USARTX>,
+            {
+                /// Configures the serial interface for smartcard (ISO 7816) communication
+                ///
+                /// The open-drain `TX` pin is the bidirectional data line, `CK` provides the card
+                /// clock. The frame format is 8 data bits with even parity and 1.5 stop bits, the
+                /// bit rate is derived from the card clock and `clocks_per_etu`, which defaults to
+                /// the answer to reset timing.
+                pub fn $usartXsmartcard(
+                    usart: pac::$USARTX,
+                    pins: (TX, CK),
+                    config: SmartcardConfig,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self {
+                    // The card clock is the peripheral clock divided by 2 * PSC, a card clock of
+                    // 0 Hz gives PSC = 0 which is rejected with the out of range prescalers
+                    let pclk = clocks.$pclkX().0;
+                    let psc = match 2 * config.clock.0 {
+                        0 => 0,
+                        divider => pclk.div_ceil(divider),
+                    };
+                    assert!((1..1 << 5).contains(&psc), "impossible smartcard clock");
+
+                    let etu = smartcard_brr(psc, config.clocks_per_etu);
+                    let serial_config = Config::default()
+                        .baudrate((pclk / etu).bps())
+                        .parity_even()
+                        .stopbits(StopBits::STOP1P5);
+
+                    Self::configure(&usart, &serial_config, clocks, apb, false, false, false);
+
+                    // Use the exact divider, one bit lasts `clocks_per_etu` card clock cycles
+                    usart.brr.write(|w| unsafe { w.bits(etu) });
+                    usart.gtpr.write(|w| w.psc().bits(psc as u8).gt().bits(config.guard_time));
+                    usart.cr2.modify(|_, w| w.clken().set_bit());
+                    usart.cr3.modify(|_, w| {
+                        w.scen()
+                            .set_bit()
+                            .nack()
+                            .bit(config.nack)
+                            .scarcnt()
+                            .bits(config.retries)
+                    });
+
+                    Self::enable(&usart);
+
+                    Serial { usart, pins }
+                }
+
+                /// Changes the number of card clock cycles per elementary time unit (bit), e.g.
+                /// after a protocol and parameters selection
+                pub fn set_clocks_per_etu(&mut self, clocks_per_etu: u16) {
+                    let psc = self.usart.gtpr.read().psc().bits() as u32;
+                    let etu = smartcard_brr(psc, clocks_per_etu);
+
+                    // BRR can only be written while the USART is disabled
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.brr.write(|w| unsafe { w.bits(etu) });
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+smartcard! {
+    USART1: (usart1_smartcard, APB2, pclk2),
+    USART2: (usart2_smartcard, APB1R1, pclk1),
+}
+
+#[cfg(any(
+    feature = "stm32l4x2",
+    feature = "stm32l4x3",
+    feature = "stm32l4x5",
+    feature = "stm32l4x6",
+))]
+smartcard! {
+    USART3: (usart3_smartcard, APB1R1, pclk1),
+}
+
+/// USART operating as a synchronous (SPI like) master
+///
+/// The clock is output on the CK pin, data is sent MSB first on TX and sampled on RX.
+pub struct UsartSpi<USART, PINS> {
+    usart: USART,
+    pins: PINS,
+}
+
+macro_rules! usart_spi {
+    ($(
+        $USARTX:ident: ($usartX:ident, $APB:ident, $pclkX:ident),
+    )+) => {
+        $(
+            impl<CK, RX, TX> UsartSpi<pac::$USARTX, (CK, RX, TX)>
+            where
+                CK: CkPin<pac::$USARTX>,
+                RX: RxPin<pac::$USARTX>,
+                TX: TxPin<pac::$USARTX>,
+            {
+                /// Configures the USART to operate as a synchronous master
+                ///
+                /// The pins are given in SPI order, `CK` is the clock (SCK), `RX` the data input
+                /// (MISO) and `TX` the data output (MOSI). The clock frequency is at most the
+                /// peripheral clock divided by 8.
+                pub fn $usartX<F>(
+                    usart: pac::$USARTX,
+                    pins: (CK, RX, TX),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                {
+                    let config = Config::default()
+                        .baudrate(freq.into().0.bps())
+                        .oversampling(Oversampling::Over8)
+                        .with_msb_first();
+
+                    Serial::<pac::$USARTX, (CK, RX, TX)>::configure(
+                        &usart, &config, clocks, apb, false, false, false,
+                    );
+
+                    // CLKEN: clock output on the CK pin
+                    // CPOL: polarity
+                    // CPHA: phase
+                    // LBCL: output a clock pulse for the last data bit, as SPI does
+                    usart.cr2.modify(|_, w| {
+                        w.clken()
+                            .set_bit()
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .lbcl()
+                            .set_bit()
+                    });
+
+                    Serial::<pac::$USARTX, (CK, RX, TX)>::enable(&usart);
+
+                    UsartSpi { usart, pins }
+                }
+
+                /// Releases the USART peripheral and associated pins
+                pub fn free(self) -> (pac::$USARTX, (CK, RX, TX)) {
+                    (self.usart, self.pins)
+                }
+            }
+
+            impl<PINS> FullDuplex<u8> for UsartSpi<pac::$USARTX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    let mut rx: Rx<pac::$USARTX> = Rx {
+                        _usart: PhantomData,
+                    };
+                    serial::Read::read(&mut rx)
+                }
+
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    let mut tx: Tx<pac::$USARTX> = Tx {
+                        _usart: PhantomData,
+                    };
+                    tx.write(byte)
+                }
+            }
+
+            impl<PINS> crate::hal::blocking::spi::transfer::Default<u8>
+                for UsartSpi<pac::$USARTX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::write::Default<u8>
+                for UsartSpi<pac::$USARTX, PINS> {}
+        )+
+    }
+}
+
+usart_spi! {
+    USART1: (usart1, APB2, pclk2),
+    USART2: (usart2, APB1R1, pclk1),
+}
+
+#[cfg(any(
+    feature = "stm32l4x2",
+    feature = "stm32l4x3",
+    feature = "stm32l4x5",
+    feature = "stm32l4x6",
+))]
+usart_spi! {
+    USART3: (usart3, APB1R1, pclk1),
 }
 
 impl<USART, PINS> fmt::Write for Serial<USART, PINS>
@@ -881,6 +2003,9 @@ pub trait RtsDePin<Instance>: private::SealedRtsDe {}
 /// Marks pins as being as being CTS pins for the given USART instance
 pub trait CtsPin<Instance>: private::SealedCts {}
 
+/// Marks pins as being as being CK (clock output) pins for the given USART instance
+pub trait CkPin<Instance>: private::SealedCk {}
+
 macro_rules! impl_pin_traits {
     (
         $(
@@ -891,6 +2016,7 @@ macro_rules! impl_pin_traits {
                         RX: $($rx:ident),*;
                         RTS_DE: $($rts_de:ident),*;
                         CTS: $($cts:ident),*;
+                        CK: $($ck:ident),*;
                     }
                 )*
             }
@@ -932,6 +2058,13 @@ macro_rules! impl_pin_traits {
                     impl CtsPin<pac::$instance> for
                         gpio::$cts<Alternate<gpio::$af, Input<Floating>>> {}
                 )*
+
+                $(
+                    impl private::SealedCk for
+                        gpio::$ck<Alternate<gpio::$af, Input<Floating>>> {}
+                    impl CkPin<pac::$instance> for
+                        gpio::$ck<Alternate<gpio::$af, Input<Floating>>> {}
+                )*
             )*
         )*
     };
@@ -944,6 +2077,7 @@ impl_pin_traits! {
             RX: PA10, PB7;
             RTS_DE: PA12, PB3;
             CTS: PA11, PB4;
+            CK: PA8, PB5;
         }
     }
     USART2: {
@@ -952,12 +2086,14 @@ impl_pin_traits! {
             RX: PA3, PD6;
             RTS_DE: PA1, PD4;
             CTS: PA0, PD3;
+            CK: PA4, PD7;
         }
         AF3: {
             TX: ;
             RX: PA15;
             RTS_DE: ;
             CTS: ;
+            CK: ;
         }
     }
 }
@@ -975,6 +2111,7 @@ impl_pin_traits! {
             RX: PB11, PC5, PC11, PD9;
             RTS_DE: PB1, PB14, PD2, PD12;
             CTS: PA6, PB13, PD11;
+            CK: PB0, PB12, PC12, PD10;
         }
     }
 }
@@ -987,6 +2124,7 @@ impl_pin_traits! {
             RX: PA1, PC11;
             RTS_DE: PA15;
             CTS: PB7;
+            CK: ;
         }
     }
     UART5: {
@@ -995,6 +2133,7 @@ impl_pin_traits! {
             RX: PD2;
             RTS_DE: PB4;
             CTS: PB5;
+            CK: ;
         }
     }
 }
@@ -1059,4 +2198,137 @@ mod private {
     pub trait SealedRx {}
     pub trait SealedRtsDe {}
     pub trait SealedCts {}
+    pub trait SealedCk {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingBuffer;
+
+    /// Ring buffer over a static buffer of `$n` bytes
+    macro_rules! ring {
+        ($n:expr) => {{
+            static mut BUFFER: [u8; $n] = [0; $n];
+            // NOTE(unsafe) each invocation has its own buffer, used by a single test
+            RingBuffer::new(unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) })
+        }};
+    }
+
+    #[test]
+    fn full_empty() {
+        let mut ring = ring!(4);
+
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+        assert_eq!((ring.len(), ring.free(), ring.capacity()), (0, 4, 4));
+
+        for byte in 1..=4 {
+            assert_eq!(ring.push(byte), Ok(()));
+        }
+        assert!(ring.is_full());
+        assert_eq!(ring.free(), 0);
+        assert_eq!(ring.push(5), Err(5));
+
+        for byte in 1..=4 {
+            assert_eq!(ring.pop(), Some(byte));
+        }
+        assert!(ring.is_empty());
+
+        ring.push(6).unwrap();
+        ring.clear();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut ring = ring!(5);
+
+        assert_eq!(ring.write(&[1, 2, 3, 4]), 4);
+        let mut data = [0; 3];
+        assert_eq!(ring.read(&mut data), 3);
+        assert_eq!(data, [1, 2, 3]);
+
+        // Written in two chunks, to the end of the buffer and from its start
+        assert_eq!(ring.write(&[5, 6, 7, 8]), 4);
+        assert!(ring.is_full());
+
+        // Read in two chunks as well
+        let mut data = [0; 5];
+        assert_eq!(ring.read(&mut data), 5);
+        assert_eq!(data, [4, 5, 6, 7, 8]);
+        assert!(ring.is_empty());
+
+        // Single bytes across the end of the buffer
+        for byte in 10..20 {
+            ring.push(byte).unwrap();
+            assert_eq!(ring.pop(), Some(byte));
+        }
+    }
+
+    #[test]
+    fn overflow_drop() {
+        let mut ring = ring!(4);
+
+        // Only the bytes that fit are stored, the rest is left to the caller
+        assert_eq!(ring.write(&[1, 2, 3]), 3);
+        assert_eq!(ring.write(&[4, 5, 6]), 1);
+        assert_eq!(ring.write(&[7]), 0);
+
+        // The receive path drops and counts the bytes pushed into a full buffer
+        let mut dropped = 0;
+        for byte in 8..12 {
+            if ring.push(byte).is_err() {
+                dropped += 1;
+            }
+        }
+        assert_eq!(dropped, 4);
+
+        let mut data = [0; 8];
+        assert_eq!(ring.read(&mut data), 4);
+        assert_eq!(&data[..4], &[1, 2, 3, 4]);
+        assert_eq!(ring.read(&mut data), 0);
+    }
+
+    #[test]
+    fn empty_buffer() {
+        let mut ring = ring!(0);
+
+        assert!(ring.is_empty() && ring.is_full());
+        assert_eq!(ring.push(1), Err(1));
+        assert_eq!(ring.write(&[1, 2]), 0);
+        assert_eq!(ring.read(&mut [0; 2]), 0);
+    }
+
+    #[test]
+    fn producer_consumer() {
+        let mut ring = ring!(7);
+        let mut produced = 0u8;
+        let mut consumed = 0u8;
+
+        // The interrupt pushes received bytes one at a time, and the application reads them
+        // in blocks of varying size, so the indices cross the end of the buffer at every offset
+        for round in 0..200usize {
+            for _ in 0..round % 5 + 1 {
+                if ring.push(produced).is_ok() {
+                    produced = produced.wrapping_add(1);
+                }
+            }
+            assert_eq!(ring.len() + ring.free(), ring.capacity());
+
+            let mut data = [0; 4];
+            let count = ring.read(&mut data[..round % 4 + 1]);
+            for byte in &data[..count] {
+                assert_eq!(*byte, consumed);
+                consumed = consumed.wrapping_add(1);
+            }
+        }
+
+        // The transmit path drains the buffer one byte at a time
+        while let Some(byte) = ring.pop() {
+            assert_eq!(byte, consumed);
+            consumed = consumed.wrapping_add(1);
+        }
+        assert_eq!(consumed, produced);
+    }
 }
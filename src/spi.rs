@@ -5,17 +5,25 @@
 //! don't have it (L432xx and L442xx don't, L452xx does). Users of this MCU variant that
 //! don't have it shouldn't attempt to use it. Relevant info is on user-manual level.
 
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ops::DerefMut;
 use core::ptr;
 use core::sync::atomic;
 use core::sync::atomic::Ordering;
 
-use crate::dma::{self, dma1, dma2, TransferPayload};
-use crate::gpio::{Alternate, Floating, Input, AF5};
+use crate::dma::{self, dma1, dma2, DMAFrame, FrameReader, FrameSender, TransferPayload};
+use crate::gpio::{Alternate, Edge, ExtiPin, Floating, Input, AF5};
+use crate::hal::blocking::spi::{Transfer, Write};
+use crate::hal::digital::v2::OutputPin;
 use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use crate::rcc::{Clocks, APB1R1, APB2};
+use crate::stm32::{EXTI, SYSCFG};
 use crate::time::Hertz;
 
 use embedded_dma::{StaticReadBuffer, StaticWriteBuffer};
+use stable_deref_trait::StableDeref;
 
 /// SPI error
 #[non_exhaustive]
@@ -29,6 +37,88 @@ pub enum Error {
     Crc,
 }
 
+/// Bit transmission order
+pub enum BitOrder {
+    /// Most significant bit first
+    MsbFirst,
+    /// Least significant bit first
+    LsbFirst,
+}
+
+/// Frame format
+pub enum FrameFormat {
+    /// Motorola frame format, the standard SPI format
+    Motorola,
+    /// TI synchronous serial frame format
+    Ti,
+}
+
+/// SPI frame configuration
+pub struct Config {
+    frame_size: u8,
+    bit_order: BitOrder,
+    nss_pulse: bool,
+    frame_format: FrameFormat,
+    crc_polynomial: Option<u16>,
+}
+
+impl Config {
+    /// Set the data frame size in bits
+    ///
+    /// Frames of up to 8 bits are transferred as `u8`, larger frames as `u16` through
+    /// [`Spi16Bit`]. Note that only sizes between 4 and 16 bits are supported, using other sizes
+    /// will cause a panic.
+    pub fn frame_size(mut self, bits: u8) -> Self {
+        assert!((4..=16).contains(&bits), "frame size out of range");
+        self.frame_size = bits;
+        self
+    }
+
+    /// Set the bit order
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Pulse NSS between consecutive frames
+    ///
+    /// Only applies to master mode with hardware NSS output and `Phase::CaptureOnFirstTransition`.
+    pub fn nss_pulse(mut self, nss_pulse: bool) -> Self {
+        self.nss_pulse = nss_pulse;
+        self
+    }
+
+    /// Set the frame format
+    ///
+    /// In TI mode the clock polarity and phase are fixed by the protocol.
+    pub fn frame_format(mut self, frame_format: FrameFormat) -> Self {
+        self.frame_format = frame_format;
+        self
+    }
+
+    /// Enable hardware CRC calculation with `polynomial`
+    ///
+    /// The CRC is 8 bits wide for frames of up to 8 bits and 16 bits wide for larger frames. It
+    /// is sent after [`Spi::send_crc`] and checked on reception, mismatches are reported as
+    /// [`Error::Crc`].
+    pub fn crc(mut self, polynomial: u16) -> Self {
+        self.crc_polynomial = Some(polynomial);
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            frame_size: 8,
+            bit_order: BitOrder::MsbFirst,
+            nss_pulse: false,
+            frame_format: FrameFormat::Motorola,
+            crc_polynomial: None,
+        }
+    }
+}
+
 #[doc(hidden)]
 mod private {
     pub trait Sealed {}
@@ -40,9 +130,18 @@ pub trait SckPin<SPI>: private::Sealed {}
 pub trait MisoPin<SPI>: private::Sealed {}
 /// MOSI pin. This trait is sealed and cannot be implemented.
 pub trait MosiPin<SPI>: private::Sealed {}
+/// NSS pin. This trait is sealed and cannot be implemented.
+pub trait NssPin<SPI>: private::Sealed {}
 
 macro_rules! pins {
-    ($spi:ident, $af:ident, SCK: [$($sck:ident),*], MISO: [$($miso:ident),*], MOSI: [$($mosi:ident),*]) => {
+    (
+        $spi:ident,
+        $af:ident,
+        SCK: [$($sck:ident),*],
+        MISO: [$($miso:ident),*],
+        MOSI: [$($mosi:ident),*],
+        NSS: [$($nss:ident),*]
+    ) => {
         $(
             impl private::Sealed for $sck<Alternate<$af, Input<Floating>>> {}
             impl SckPin<$spi> for $sck<Alternate<$af, Input<Floating>>> {}
@@ -55,17 +154,60 @@ macro_rules! pins {
             impl private::Sealed for $mosi<Alternate<$af, Input<Floating>>> {}
             impl MosiPin<$spi> for $mosi<Alternate<$af, Input<Floating>>> {}
         )*
+        $(
+            impl private::Sealed for $nss<Alternate<$af, Input<Floating>>> {}
+            impl NssPin<$spi> for $nss<Alternate<$af, Input<Floating>>> {}
+        )*
     }
 }
 
-/// SPI peripheral operating in full duplex master mode
-pub struct Spi<SPI, PINS> {
+/// Placeholder for the MISO pin in modes that do not use it
+pub struct NoMiso;
+
+/// Placeholder for the MOSI pin in modes that do not use it
+pub struct NoMosi;
+
+/// NSS input of an SPI slave, see for example [`Spi::spi1_slave_with_nss`]
+pub struct SlaveNss<NSS>(NSS);
+
+/// Full duplex mode (type state)
+pub struct Duplex;
+
+/// Half duplex (3-wire) master mode (type state)
+pub struct HalfDuplex;
+
+/// Receive only master mode (type state)
+pub struct RxOnly;
+
+/// SPI peripheral operating in full duplex, half duplex or receive only mode
+///
+/// The blocking and non-blocking embedded-hal traits are only implemented in [`Duplex`] mode,
+/// the other modes provide `transmit` and `receive` methods instead.
+pub struct Spi<SPI, PINS, MODE = Duplex> {
     spi: SPI,
     pins: PINS,
+    _mode: PhantomData<MODE>,
+}
+
+/// SPI peripheral transferring frames of more than 8 bits
+///
+/// Created through `Spi::into_16bit`, the frame size is set with [`Spi::configure`].
+pub struct Spi16Bit<SPI, PINS> {
+    spi: Spi<SPI, PINS>,
 }
 
 macro_rules! hal {
-    ($($SPIX:ident: ($spiX:ident, $spiX_slave:ident, $APBX:ident, $spiXen:ident, $spiXrst:ident, $pclkX:ident),)+) => {
+    ($($SPIX:ident: (
+        $spiX:ident,
+        $spiX_slave:ident,
+        $spiX_slave_with_nss:ident,
+        $spiX_half_duplex:ident,
+        $spiX_rx_only:ident,
+        $APBX:ident,
+        $spiXen:ident,
+        $spiXrst:ident,
+        $pclkX:ident
+    ),)+) => {
         $(
             impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
                 /// Configures the SPI peripheral to operate in full duplex master mode
@@ -83,6 +225,25 @@ macro_rules! hal {
                     MISO: MisoPin<$SPIX>,
                     MOSI: MosiPin<$SPIX>,
                 {
+                    Self::init_master(&spi, mode, freq.into(), clocks, apb2, false, false);
+
+                    Spi {
+                        spi,
+                        pins,
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Resets the SPI and configures it in master mode
+                fn init_master(
+                    spi: &$SPIX,
+                    mode: Mode,
+                    freq: Hertz,
+                    clocks: Clocks,
+                    apb2: &mut $APBX,
+                    bidimode: bool,
+                    rxonly: bool,
+                ) {
                     // enable or reset $SPIX
                     apb2.enr().modify(|_, w| w.$spiXen().set_bit());
                     apb2.rstr().modify(|_, w| w.$spiXrst().set_bit());
@@ -97,18 +258,20 @@ macro_rules! hal {
                             w.frxth().set_bit().ds().bits(0b111).ssoe().clear_bit()
                         });
 
-                    let br = Self::compute_baud_rate(clocks.$pclkX(), freq.into());
+                    let br = Self::compute_baud_rate(clocks.$pclkX(), freq);
 
                     // CPHA: phase
                     // CPOL: polarity
                     // MSTR: master mode
                     // BR: 1 MHz
-                    // SPE: SPI disabled
+                    // SPE: SPI enabled, unless receive only as the clock would start right away
                     // LSBFIRST: MSB first
                     // SSM: enable software slave management (NSS pin free for other uses)
                     // SSI: set nss high = master mode
                     // CRCEN: hardware CRC calculation disabled
-                    // BIDIMODE: 2 line unidirectional (full duplex)
+                    // BIDIMODE: 2 line unidirectional (full duplex) or 1 line bidirectional
+                    // BIDIOE: output enabled in bidirectional mode
+                    // RXONLY: receive only in 2 line mode
                     spi.cr1.write(|w| unsafe {
                         w.cpha()
                             .bit(mode.phase == Phase::CaptureOnSecondTransition)
@@ -119,7 +282,7 @@ macro_rules! hal {
                             .br()
                             .bits(br)
                             .spe()
-                            .set_bit()
+                            .bit(!rxonly)
                             .lsbfirst()
                             .clear_bit()
                             .ssi()
@@ -129,10 +292,12 @@ macro_rules! hal {
                             .crcen()
                             .clear_bit()
                             .bidimode()
-                            .clear_bit()
+                            .bit(bidimode)
+                            .bidioe()
+                            .bit(bidimode)
+                            .rxonly()
+                            .bit(rxonly)
                     });
-
-                    Spi { spi, pins }
                 }
 
                 pub fn $spiX_slave(spi: $SPIX, pins: (SCK, MISO, MOSI), mode: Mode, apb2: &mut $APBX,) -> Self
@@ -141,6 +306,43 @@ macro_rules! hal {
                     MISO: MisoPin<$SPIX>,
                     MOSI: MosiPin<$SPIX>,
                 {
+                    Self::init_slave(&spi, mode, apb2);
+
+                    Spi {
+                        spi,
+                        pins,
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Configures the SPI peripheral to operate in slave mode, selected by the `NSS`
+                /// pin
+                ///
+                /// The master releasing NSS marks the end of a transaction, see
+                /// [`Spi::listen_end_of_frame`] and [`Spi::end_of_frame`].
+                pub fn $spiX_slave_with_nss<NSS>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    mode: Mode,
+                    apb2: &mut $APBX,
+                ) -> Spi<$SPIX, (SCK, MISO, MOSI, SlaveNss<NSS>)>
+                where
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    Self::init_slave(&spi, mode, apb2);
+
+                    let (sck, miso, mosi, nss) = pins;
+                    Spi {
+                        spi,
+                        pins: (sck, miso, mosi, SlaveNss(nss)),
+                        _mode: PhantomData,
+                    }
+                }
+
+                fn init_slave(spi: &$SPIX, mode: Mode, apb2: &mut $APBX) {
                     // enable or reset $SPIX
                     apb2.enr().modify(|_, w| w.$spiXen().set_bit());
                     apb2.rstr().modify(|_, w| w.$spiXrst().set_bit());
@@ -151,7 +353,7 @@ macro_rules! hal {
                     // BIDIMODE: 2 line unidirectional (full duplex)
                     // LSBFIRST: MSB first
                     // CRCEN: hardware CRC calculation disabled
-                    // MSTR: master mode
+                    // MSTR: slave mode
                     // SSM: disable software slave management (NSS pin not free for other uses)
                     // SPE: SPI disabled
                     spi.cr1.write(|w| {
@@ -177,10 +379,8 @@ macro_rules! hal {
                     spi.cr2
                         .write(|w| unsafe { w.ds().bits(0b111).frxth().set_bit() });
 
-                    // SPE: SPI enabled
-                    spi.cr1.write(|w| w.spe().set_bit());
-
-                    Spi { spi, pins }
+                    // SPE: SPI enabled, keeping the mode written above
+                    spi.cr1.modify(|_, w| w.spe().set_bit());
                 }
 
                 pub fn clear_overrun(&mut self) {
@@ -188,14 +388,317 @@ macro_rules! hal {
                     self.spi.sr.read().ovr();
                 }
 
+                /// Switches to hardware slave select management, with `nss` driven low by the
+                /// SPI while it is enabled
+                ///
+                /// The SPI is left disabled, transactions have to be framed by
+                /// [`Spi::assert_nss`] and [`Spi::deassert_nss`], or NSS pulses can be generated
+                /// between frames with [`Config::nss_pulse`].
+                pub fn with_hardware_nss<NSS>(self, nss: NSS) -> Spi<$SPIX, (SCK, MISO, MOSI, NSS)>
+                where
+                    NSS: NssPin<$SPIX>,
+                {
+                    self.wait_idle();
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+
+                    // SSM: disable software slave management
+                    // SSOE: Slave Select output enabled
+                    self.spi.cr1.modify(|_, w| w.ssm().clear_bit());
+                    self.spi.cr2.modify(|_, w| w.ssoe().set_bit());
+
+                    let (sck, miso, mosi) = self.pins;
+                    Spi {
+                        spi: self.spi,
+                        pins: (sck, miso, mosi, nss),
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<SCK, MISO, MOSI, NSS> Spi<$SPIX, (SCK, MISO, MOSI, NSS)>
+            where
+                NSS: NssPin<$SPIX>,
+            {
+                /// Enables the SPI, which drives NSS low
+                pub fn assert_nss(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                /// Waits for the ongoing transfer to complete and disables the SPI, which releases
+                /// NSS
+                pub fn deassert_nss(&mut self) {
+                    self.wait_idle();
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI, NSS)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<SCK, MISO, MOSI, NSS> Spi<$SPIX, (SCK, MISO, MOSI, SlaveNss<NSS>)> {
+                /// Fills the transmit FIFO with the start of the next reply, before the master
+                /// selects the slave
+                ///
+                /// Returns the number of bytes that were queued, the FIFO holds up to 4 bytes.
+                pub fn preload(&mut self, words: &[u8]) -> usize {
+                    let mut queued = 0;
+
+                    for word in words {
+                        // FTLVL: 0b11 means the transmit FIFO is full
+                        if self.spi.sr.read().ftlvl().bits() == 0b11 {
+                            break;
+                        }
+
+                        // NOTE(write_volatile) see note in the `FullDuplex` implementation
+                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, *word) }
+                        queued += 1;
+                    }
+
+                    queued
+                }
+
+                /// Generates an EXTI interrupt when the master releases NSS, which marks the end of
+                /// a frame
+                pub fn listen_end_of_frame(
+                    &mut self,
+                    syscfg: &mut SYSCFG,
+                    exti: &mut EXTI,
+                    apb2: &mut APB2,
+                ) where
+                    NSS: ExtiPin,
+                {
+                    let nss = &mut (self.pins.3).0;
+                    nss.make_interrupt_source(syscfg, apb2);
+                    nss.trigger_on_edge(exti, Edge::Rising);
+                    nss.enable_interrupt(exti);
+                }
+
+                /// Stops generating an EXTI interrupt at the end of a frame
+                pub fn unlisten_end_of_frame(&mut self, exti: &mut EXTI)
+                where
+                    NSS: ExtiPin,
+                {
+                    (self.pins.3).0.disable_interrupt(exti);
+                }
+
+                /// Checks if the master has released NSS since the flag was last cleared
+                pub fn check_end_of_frame(&mut self, clear: bool) -> bool
+                where
+                    NSS: ExtiPin,
+                {
+                    let nss = &mut (self.pins.3).0;
+                    let pending = nss.check_interrupt();
+
+                    if clear {
+                        nss.clear_interrupt_pending_bit();
+                    }
+
+                    pending
+                }
+
+                /// Recovers the SPI for the next frame, call this once the master has released NSS
+                ///
+                /// Received data that has not been read and reply data that has not been clocked
+                /// out are dropped. The SPI of this family does not flag underruns: when the
+                /// master clocks out more data than was queued the slave sends stale data, which is
+                /// flushed here as well. Returns [`Error::Overrun`] if received data was lost
+                /// during the frame.
+                pub fn end_of_frame(&mut self) -> Result<(), Error> {
+                    let overrun = self.spi.sr.read().ovr().bit_is_set();
+                    let cr1 = self.spi.cr1.read().bits();
+                    let cr2 = self.spi.cr2.read().bits();
+                    let crcpr = self.spi.crcpr.read().bits();
+
+                    // The transmit FIFO can only be flushed by resetting the peripheral, the
+                    // configuration is restored afterwards
+                    cortex_m::interrupt::free(|_| {
+                        // NOTE(unsafe) the reset register is modified in a critical section, and
+                        // only the reset bit of this SPI is changed
+                        let mut apb = unsafe { $APBX::steal() };
+                        apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                        apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+                    });
+
+                    self.spi.crcpr.write(|w| unsafe { w.bits(crcpr) });
+                    self.spi.cr2.write(|w| unsafe { w.bits(cr2) });
+                    self.spi.cr1.write(|w| unsafe { w.bits(cr1) }.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().bit(cr1 & (1 << 6) != 0));
+
+                    if overrun {
+                        Err(Error::Overrun)
+                    } else {
+                        Ok(())
+                    }
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI, NSS)) {
+                    let (sck, miso, mosi, nss) = self.pins;
+                    (self.spi, (sck, miso, mosi, nss.0))
+                }
+            }
+
+            impl<SCK, MOSI> Spi<$SPIX, (SCK, NoMiso, MOSI), HalfDuplex>
+            where
+                SCK: SckPin<$SPIX>,
+                MOSI: MosiPin<$SPIX>,
+            {
+                /// Configures the SPI peripheral to operate in half duplex (3-wire) master mode
+                ///
+                /// Data is sent and received on the single `MOSI` pin, the direction is switched by
+                /// [`Spi::transmit`] and [`Spi::receive`].
+                pub fn $spiX_half_duplex<F>(
+                    spi: $SPIX,
+                    pins: (SCK, NoMiso, MOSI),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb2: &mut $APBX,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                {
+                    Spi::<$SPIX, (SCK, NoMiso, MOSI)>::init_master(
+                        &spi,
+                        mode,
+                        freq.into(),
+                        clocks,
+                        apb2,
+                        true,
+                        false,
+                    );
+
+                    Spi {
+                        spi,
+                        pins,
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Sends `words` with the data pin as output
+                pub fn transmit(&mut self, words: &[u8]) -> Result<(), Error> {
+                    self.spi.cr1.modify(|_, w| w.bidioe().set_bit().spe().set_bit());
+
+                    for word in words {
+                        nb::block!(self.send_u8(*word))?;
+                    }
+                    self.wait_idle();
+
+                    Ok(())
+                }
+
+                /// Fills `words` with the data pin as input
+                ///
+                /// The clock runs continuously while receiving, so the peripheral may clock in a
+                /// few frames more than requested before it is stopped. These are discarded.
+                pub fn receive(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    // Switching to input starts the clock
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.bidioe().clear_bit().spe().set_bit());
+
+                    let result = self.receive_words(words);
+
+                    // Switch back to output, which stops the clock
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.discard_rx_fifo();
+                    self.spi.cr1.modify(|_, w| w.bidioe().set_bit().spe().set_bit());
+
+                    result
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, NoMiso, MOSI)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<SCK, MISO> Spi<$SPIX, (SCK, MISO, NoMosi), RxOnly>
+            where
+                SCK: SckPin<$SPIX>,
+                MISO: MisoPin<$SPIX>,
+            {
+                /// Configures the SPI peripheral to operate in receive only master mode
+                pub fn $spiX_rx_only<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, NoMosi),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb2: &mut $APBX,
+                ) -> Self
+                where
+                    F: Into<Hertz>,
+                {
+                    Spi::<$SPIX, (SCK, MISO, NoMosi)>::init_master(
+                        &spi,
+                        mode,
+                        freq.into(),
+                        clocks,
+                        apb2,
+                        false,
+                        true,
+                    );
+
+                    Spi {
+                        spi,
+                        pins,
+                        _mode: PhantomData,
+                    }
+                }
+
+                /// Fills `words` with received data
+                ///
+                /// The clock runs continuously while receiving, so the peripheral may clock in a
+                /// few frames more than requested before it is stopped. These are discarded.
+                pub fn receive(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    // Enabling the SPI starts the clock
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+
+                    let result = self.receive_words(words);
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.discard_rx_fifo();
+
+                    result
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, NoMosi)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS, MODE> Spi<$SPIX, PINS, MODE> {
                 /// Change the baud rate of the SPI
                 pub fn reclock<F>(&mut self, freq: F, clocks: Clocks)
                     where F: Into<Hertz>
                 {
+                    let enabled = self.spi.cr1.read().spe().bit_is_set();
                     self.spi.cr1.modify(|_, w| w.spe().clear_bit());
                     self.spi.cr1.modify(|_, w| {
                         unsafe {w.br().bits(Self::compute_baud_rate(clocks.$pclkX(), freq.into()));}
-                        w.spe().set_bit()
+                        w.spe().bit(enabled)
+                    });
+                }
+
+                /// Change the clock polarity and phase of the SPI
+                pub fn set_mode(&mut self, mode: Mode) {
+                    let enabled = self.spi.cr1.read().spe().bit_is_set();
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .spe()
+                            .bit(enabled)
                     });
                 }
 
@@ -213,9 +716,108 @@ macro_rules! hal {
                     }
                 }
 
-                /// Releases the SPI peripheral and associated pins
-                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
-                    (self.spi, self.pins)
+                fn receive_words(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    for word in words.iter_mut() {
+                        *word = nb::block!(self.read_u8())?;
+                    }
+
+                    Ok(())
+                }
+
+                /// Waits for the transmit FIFO to drain and the bus to become idle
+                fn wait_idle(&self) {
+                    while self.spi.sr.read().ftlvl().bits() != 0 {}
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                }
+
+                /// Waits for the bus to become idle and empties the receive FIFO
+                fn discard_rx_fifo(&self) {
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    while self.spi.sr.read().frlvl().bits() != 0 {
+                        // NOTE(read_volatile) read only 1 byte
+                        let _ = unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u8) };
+                    }
+                    let _ = self.spi.sr.read().ovr();
+                }
+
+                /// Change the frame size, bit order, frame format and CRC settings of the SPI
+                pub fn configure(&mut self, config: Config) {
+                    // The frame layout can only be changed while the SPI is disabled
+                    let enabled = self.spi.cr1.read().spe().bit_is_set();
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+
+                    // DS: data size
+                    // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
+                    //        8-bit for frames of up to 8 bits, 16-bit otherwise
+                    // NSSP: NSS pulse management
+                    // FRF: frame format
+                    self.spi.cr2.modify(|_, w| unsafe {
+                        w.ds()
+                            .bits(config.frame_size - 1)
+                            .frxth()
+                            .bit(config.frame_size <= 8)
+                            .nssp()
+                            .bit(config.nss_pulse)
+                            .frf()
+                            .bit(match config.frame_format {
+                                FrameFormat::Motorola => false,
+                                FrameFormat::Ti => true,
+                            })
+                    });
+
+                    if let Some(polynomial) = config.crc_polynomial {
+                        self.spi.crcpr.write(|w| unsafe { w.crcpoly().bits(polynomial) });
+                    }
+
+                    // LSBFIRST: bit order
+                    // CRCL (named DFF in the PAC): CRC length
+                    // CRCEN: hardware CRC calculation
+                    self.spi.cr1.modify(|_, w| {
+                        w.lsbfirst()
+                            .bit(match config.bit_order {
+                                BitOrder::MsbFirst => false,
+                                BitOrder::LsbFirst => true,
+                            })
+                            .dff()
+                            .bit(config.frame_size > 8)
+                            .crcen()
+                            .bit(config.crc_polynomial.is_some())
+                    });
+
+                    self.spi.cr1.modify(|_, w| w.spe().bit(enabled));
+                }
+
+                /// Transmit the CRC after the frame currently being sent
+                ///
+                /// Call this after writing the last data frame of a transaction. The CRC received
+                /// in exchange has to be read like a regular frame and is checked by hardware.
+                pub fn send_crc(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                }
+
+                /// Restart the CRC calculation, e.g. at the start of a new transaction
+                pub fn reset_crc(&mut self) {
+                    let enabled = self.spi.cr1.read().spe().bit_is_set();
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().set_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().bit(enabled));
+                }
+
+                fn check_errors(&mut self) -> Result<(), Error> {
+                    let sr = self.spi.sr.read();
+
+                    if sr.ovr().bit_is_set() {
+                        Err(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        Err(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        // CRCERR is cleared by writing 0 to it
+                        self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                        Err(Error::Crc)
+                    } else {
+                        Ok(())
+                    }
                 }
             }
 
@@ -223,47 +825,103 @@ macro_rules! hal {
                 type Error = Error;
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
-                    let sr = self.spi.sr.read();
+                    self.read_u8()
+                }
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.rxne().bit_is_set() {
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    self.send_u8(byte)
+                }
+            }
+
+            impl<PINS, MODE> Spi<$SPIX, PINS, MODE> {
+                fn read_u8(&mut self) -> nb::Result<u8, Error> {
+                    self.check_errors()?;
+
+                    if self.spi.sr.read().rxne().bit_is_set() {
                         // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
                         // reading a half-word)
-                        return Ok(unsafe {
-                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8)
-                        });
+                        Ok(unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u8) })
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
 
-                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
-                    let sr = self.spi.sr.read();
+                fn send_u8(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    self.check_errors()?;
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.txe().bit_is_set() {
+                    if self.spi.sr.read().txe().bit_is_set() {
                         // NOTE(write_volatile) see note above
                         unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
-                        return Ok(());
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                /// Converts the SPI into one transferring frames of more than 8 bits, see
+                /// [`Config::frame_size`]
+                pub fn into_16bit(self) -> Spi16Bit<$SPIX, PINS> {
+                    Spi16Bit { spi: self }
+                }
+            }
+
+            impl<PINS> Spi16Bit<$SPIX, PINS> {
+                /// Converts back into an SPI transferring frames of up to 8 bits
+                pub fn into_8bit(self) -> Spi<$SPIX, PINS> {
+                    self.spi
+                }
+            }
+
+            impl<PINS> FullDuplex<u16> for Spi16Bit<$SPIX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    let spi = &mut self.spi;
+                    spi.check_errors()?;
+
+                    if spi.spi.sr.read().rxne().bit_is_set() {
+                        // NOTE(read_volatile) read a half-word
+                        Ok(unsafe { ptr::read_volatile(&spi.spi.dr as *const _ as *const u16) })
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let spi = &mut self.spi;
+                    spi.check_errors()?;
+
+                    if spi.spi.sr.read().txe().bit_is_set() {
+                        // NOTE(write_volatile) write a half-word
+                        unsafe { ptr::write_volatile(&spi.spi.dr as *const _ as *mut u16, word) }
+                        Ok(())
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
             }
 
             impl<PINS> crate::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
 
             impl<PINS> crate::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::transfer::Default<u16>
+                for Spi16Bit<$SPIX, PINS> {}
+
+            impl<PINS> crate::hal::blocking::spi::write::Default<u16>
+                for Spi16Bit<$SPIX, PINS> {}
+
+            impl<PINS, MODE> Reconfigure for Spi<$SPIX, PINS, MODE> {
+                fn set_mode(&mut self, mode: Mode) {
+                    Spi::<$SPIX, PINS, MODE>::set_mode(self, mode)
+                }
+
+                fn reclock(&mut self, freq: Hertz, clocks: Clocks) {
+                    Spi::<$SPIX, PINS, MODE>::reclock(self, freq, clocks)
+                }
+            }
         )+
     }
 }
@@ -289,7 +947,7 @@ use crate::stm32::SPI1;
     feature = "stm32l4x6"
 ))]
 hal! {
-    SPI1: (spi1, spi1_slave, APB2, spi1en, spi1rst, pclk2),
+    SPI1: (spi1, spi1_slave, spi1_slave_with_nss, spi1_half_duplex, spi1_rx_only, APB2, spi1en, spi1rst, pclk2),
 }
 
 #[cfg(any(
@@ -302,10 +960,11 @@ hal! {
 pins!(SPI1, AF5,
     SCK: [PA5, PB3, PE13],
     MISO: [PA6, PB4, PE14],
-    MOSI: [PA7, PB5, PE15]);
+    MOSI: [PA7, PB5, PE15],
+    NSS: [PA4, PA15, PB0, PE12]);
 
 #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6"))]
-pins!(SPI1, AF5, SCK: [PG2], MISO: [PG3], MOSI: [PG4]);
+pins!(SPI1, AF5, SCK: [PG2], MISO: [PG3], MOSI: [PG4], NSS: [PG5]);
 
 #[cfg(any(
     feature = "stm32l4x1",
@@ -322,7 +981,7 @@ use crate::{gpio::AF6, stm32::SPI3};
     feature = "stm32l4x6",
 ))]
 hal! {
-    SPI3: (spi3, spi3_slave, APB1R1, spi3en, spi3rst, pclk1),
+    SPI3: (spi3, spi3_slave, spi3_slave_with_nss, spi3_half_duplex, spi3_rx_only, APB1R1, spi3en, spi3rst, pclk1),
 }
 
 #[cfg(any(
@@ -334,10 +993,11 @@ hal! {
 pins!(SPI3, AF6,
     SCK: [PB3, PC10],
     MISO: [PB4, PC11],
-    MOSI: [PB5, PC12]);
+    MOSI: [PB5, PC12],
+    NSS: [PA4, PA15]);
 
 #[cfg(any(feature = "stm32l4x5", feature = "stm32l4x6",))]
-pins!(SPI3, AF6, SCK: [PG9], MISO: [PG10], MOSI: [PG11]);
+pins!(SPI3, AF6, SCK: [PG9], MISO: [PG10], MOSI: [PG11], NSS: [PG12]);
 
 #[cfg(any(
     feature = "stm32l4x1",
@@ -356,7 +1016,7 @@ use crate::stm32::SPI2;
     feature = "stm32l4x6",
 ))]
 hal! {
-    SPI2: (spi2, spi2_slave, APB1R1, spi2en, spi2rst, pclk1),
+    SPI2: (spi2, spi2_slave, spi2_slave_with_nss, spi2_half_duplex, spi2_rx_only, APB1R1, spi2en, spi2rst, pclk1),
 }
 
 #[cfg(any(
@@ -369,7 +1029,136 @@ hal! {
 pins!(SPI2, AF5,
     SCK: [PB13, PB10, PD1],
     MISO: [PB14, PC2, PD3],
-    MOSI: [PB15, PC3, PD4]);
+    MOSI: [PB15, PC3, PD4],
+    NSS: [PB9, PB12, PD0]);
+
+/// SPI bus settings that can be changed per [`SpiDevice`]
+pub trait Reconfigure {
+    /// Change the clock polarity and phase
+    fn set_mode(&mut self, mode: Mode);
+
+    /// Change the baud rate
+    fn reclock(&mut self, freq: Hertz, clocks: Clocks);
+}
+
+/// Provides exclusive access to a shared SPI bus
+pub trait BusMutex {
+    /// The shared bus
+    type Bus;
+
+    /// Runs `f` with exclusive access to the bus
+    fn lock<R, F: FnOnce(&mut Self::Bus) -> R>(&self, f: F) -> R;
+}
+
+/// Sharing between devices used from the same execution context
+impl<T> BusMutex for RefCell<T> {
+    type Bus = T;
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.borrow_mut())
+    }
+}
+
+/// Sharing between devices used from different interrupt priorities
+impl<T> BusMutex for cortex_m::interrupt::Mutex<RefCell<T>> {
+    type Bus = T;
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        cortex_m::interrupt::free(|cs| f(&mut self.borrow(cs).borrow_mut()))
+    }
+}
+
+/// A device on a shared SPI bus, selected through a GPIO chip select pin
+///
+/// Chip select is driven low for the duration of each `transfer` or `write`. If the device was
+/// created with settings, the bus is switched to its mode and frequency first.
+pub struct SpiDevice<'a, M, CS> {
+    bus: &'a M,
+    cs: CS,
+    settings: Option<(Mode, Hertz, Clocks)>,
+}
+
+impl<'a, M, CS> SpiDevice<'a, M, CS>
+where
+    M: BusMutex,
+    CS: OutputPin<Error = Infallible>,
+{
+    /// Creates a device using the current bus settings, `cs` is driven high (deselected)
+    pub fn new(bus: &'a M, mut cs: CS) -> Self {
+        cs.set_high().ok();
+
+        SpiDevice {
+            bus,
+            cs,
+            settings: None,
+        }
+    }
+
+    /// Creates a device that switches the bus to `mode` and `freq` before each transaction
+    pub fn with_settings<F>(bus: &'a M, cs: CS, mode: Mode, freq: F, clocks: Clocks) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        let mut device = Self::new(bus, cs);
+        device.settings = Some((mode, freq.into(), clocks));
+        device
+    }
+
+    /// Releases the chip select pin
+    pub fn free(self) -> CS {
+        self.cs
+    }
+
+    fn transaction<R, F>(&mut self, f: F) -> R
+    where
+        M::Bus: Reconfigure,
+        F: FnOnce(&mut M::Bus) -> R,
+    {
+        let cs = &mut self.cs;
+        let settings = &self.settings;
+
+        self.bus.lock(|bus| {
+            if let Some((mode, freq, clocks)) = settings {
+                bus.set_mode(*mode);
+                bus.reclock(*freq, *clocks);
+            }
+
+            cs.set_low().ok();
+            let result = f(bus);
+            cs.set_high().ok();
+
+            result
+        })
+    }
+}
+
+impl<'a, M, CS> Transfer<u8> for SpiDevice<'a, M, CS>
+where
+    M: BusMutex,
+    M::Bus: Reconfigure + Transfer<u8, Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+{
+    type Error = Error;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Error> {
+        self.transaction(|bus| bus.transfer(words).map(|_| ()))?;
+
+        Ok(words)
+    }
+}
+
+impl<'a, M, CS> Write<u8> for SpiDevice<'a, M, CS>
+where
+    M: BusMutex,
+    M::Bus: Reconfigure + Write<u8, Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+{
+    type Error = Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        self.transaction(|bus| bus.write(words))
+    }
+}
 
 pub struct SpiPayload<SPI, PINS> {
     spi: Spi<SPI, PINS>,
@@ -382,7 +1171,7 @@ pub type SpiTxDma<SPI, PINS, CHANNEL> = dma::TxDma<SpiPayload<SPI, PINS>, CHANNE
 pub type SpiRxTxDma<SPI, PINS, RXCH, TXCH> = dma::RxTxDma<SpiPayload<SPI, PINS>, RXCH, TXCH>;
 
 macro_rules! spi_dma {
-    ($SPIX:ident, $RX_CH:path, $RX_CHX:ident, $RX_MAPX:ident, $TX_CH:path, $TX_CHX:ident, $TX_MAPX:ident) => {
+    ($SPIX:ident, $APBX:ident, $RX_CH:path, $RX_CHX:ident, $RX_MAPX:ident, $TX_CH:path, $TX_CHX:ident, $TX_MAPX:ident) => {
         impl<PINS> dma::Receive for SpiRxDma<$SPIX, PINS, $RX_CH> {
             type RxChannel = $RX_CH;
             type TransmittedWord = u8;
@@ -549,6 +1338,99 @@ macro_rules! spi_dma {
             }
         }
 
+        impl<SCK, MISO, MOSI, NSS> SpiRxDma<$SPIX, (SCK, MISO, MOSI, SlaveNss<NSS>), $RX_CH> {
+            /// Creates a frame reader for a slave, each frame is ended by the master releasing NSS
+            ///
+            /// Call [`FrameReader::end_of_frame_interrupt`] from the EXTI interrupt enabled with
+            /// [`Spi::listen_end_of_frame`].
+            pub fn frame_reader<BUFFER, const N: usize>(
+                mut self,
+                buffer: BUFFER,
+            ) -> FrameReader<BUFFER, Self, N>
+            where
+                BUFFER: Sized + StableDeref<Target = DMAFrame<N>> + DerefMut + 'static,
+            {
+                // Setup DMA transfer
+                let buf = &*buffer;
+                self.channel
+                    .set_memory_address(unsafe { buf.buffer_address_for_dma() } as u32, true);
+                self.channel.set_transfer_length(buf.max_len() as u16);
+
+                // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                // the next statement, which starts the DMA transfer
+                atomic::compiler_fence(Ordering::Release);
+
+                self.start();
+
+                FrameReader::new(buffer, self, 0)
+            }
+        }
+
+        impl<BUFFER, SCK, MISO, MOSI, NSS, const N: usize>
+            FrameReader<BUFFER, SpiRxDma<$SPIX, (SCK, MISO, MOSI, SlaveNss<NSS>), $RX_CH>, N>
+        where
+            BUFFER: Sized + StableDeref<Target = DMAFrame<N>> + DerefMut + 'static,
+            NSS: ExtiPin,
+        {
+            /// This function should be called from the EXTI interrupt of the NSS pin
+            ///
+            /// Returns the frame received since the previous call, and [`Error::Overrun`] if data
+            /// was lost during the frame. The SPI is recovered for the next frame, see
+            /// [`Spi::end_of_frame`].
+            pub fn end_of_frame_interrupt(
+                &mut self,
+                next_frame: BUFFER,
+            ) -> (BUFFER, Result<(), Error>) {
+                let frame = self.receiver_timeout_interrupt(next_frame);
+
+                let spi = &mut self.payload_mut().payload.spi;
+                spi.check_end_of_frame(true);
+                let result = spi.end_of_frame();
+
+                (frame, result)
+            }
+
+            /// Fills the transmit FIFO with the start of the next reply, see [`Spi::preload`]
+            pub fn preload(&mut self, words: &[u8]) -> usize {
+                self.payload_mut().payload.spi.preload(words)
+            }
+        }
+
+        impl<SCK, MISO, MOSI, NSS> SpiTxDma<$SPIX, (SCK, MISO, MOSI, SlaveNss<NSS>), $TX_CH> {
+            /// Creates a frame sender for a slave, the frames are clocked out by the master
+            ///
+            /// The transfer of `first_frame` is started right away, as the DMA channel has to be
+            /// enabled before the SPI requests data from it. The next frames are queued with
+            /// [`FrameSender::send`].
+            pub fn frame_sender<BUFFER, const N: usize>(
+                mut self,
+                first_frame: BUFFER,
+            ) -> FrameSender<BUFFER, Self, N>
+            where
+                BUFFER: Sized + StableDeref<Target = DMAFrame<N>> + DerefMut + 'static,
+            {
+                // Follows RM 40.4.9: SPI disabled during setup, DMA channel enabled, then TXDMAEN
+                // and finally SPE
+                self.payload.spi.spi.cr1.modify(|_, w| w.spe().clear_bit());
+
+                let buf = &*first_frame;
+                self.channel
+                    .set_memory_address(buf.buffer_as_ptr() as u32, true);
+                self.channel.set_transfer_length(buf.len() as u16);
+
+                // NOTE(compiler_fence) operations on `first_frame` should not be reordered after
+                // the next statement, which starts the DMA transfer
+                atomic::compiler_fence(Ordering::Release);
+                self.channel.start();
+
+                let spi = &self.payload.spi.spi;
+                spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+                spi.cr1.modify(|_, w| w.spe().set_bit());
+
+                FrameSender::sending(self, first_frame)
+            }
+        }
+
         impl<PINS> SpiRxDma<$SPIX, PINS, $RX_CH> {
             pub fn split(mut self) -> (Spi<$SPIX, PINS>, $RX_CH) {
                 self.stop();
@@ -777,19 +1659,19 @@ macro_rules! spi_dma {
     };
 }
 
-spi_dma!(SPI1, dma1::C2, c2s, map1, dma1::C3, c3s, map1);
+spi_dma!(SPI1, APB2, dma1::C2, c2s, map1, dma1::C3, c3s, map1);
 #[cfg(any(
     feature = "stm32l4x1",
     feature = "stm32l4x3",
     feature = "stm32l4x5",
     feature = "stm32l4x6",
 ))]
-spi_dma!(SPI2, dma1::C4, c4s, map1, dma1::C5, c5s, map1);
-// spi_dma!(SPI1, dma2::C3, c3s, map4, dma2::C4, c4s, map4);
+spi_dma!(SPI2, APB1R1, dma1::C4, c4s, map1, dma1::C5, c5s, map1);
+// spi_dma!(SPI1, APB2, dma2::C3, c3s, map4, dma2::C4, c4s, map4);
 #[cfg(any(
     feature = "stm32l4x1",
     feature = "stm32l4x2",
     feature = "stm32l4x5",
     feature = "stm32l4x6",
 ))]
-spi_dma!(SPI3, dma2::C1, c1s, map3, dma2::C2, c2s, map3);
+spi_dma!(SPI3, APB1R1, dma2::C1, c1s, map3, dma2::C2, c2s, map3);